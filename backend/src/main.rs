@@ -14,7 +14,12 @@ use rocket::Request;
 use rocket::Response;
 use rocket_contrib::json::Json;
 use rocket_contrib::serve::StaticFiles;
+use rustmith_common::format::lrc::parse_lrc;
+use rustmith_common::schema::DocumentError;
+use rustmith_common::stats::TrackStats;
 use rustmith_common::track::*;
+use rustmith_common::validate::Severity;
+use std::collections::HashMap;
 use std::num::ParseIntError;
 use uuid::Uuid;
 
@@ -45,39 +50,126 @@ impl From<ParseIntError> for ApiError {
     }
 }
 
+impl From<DocumentError> for ApiError {
+    fn from(_: DocumentError) -> Self {
+        ApiError::InvalidFormatError
+    }
+}
+
 impl From<serde_json::error::Error> for ApiError {
     fn from(_: serde_json::error::Error) -> Self {
         ApiError::InvalidFormatError
     }
 }
 
+/// tracks that would break the renderer or have nothing to play are never stored
+fn check_playable(track: &Track) -> Result<(), ApiError> {
+    if track.arrangements.is_empty() || track.validate().iter().any(|(_, d)| d.severity() == Severity::Error) {
+        return Err(ApiError::InvalidFormatError);
+    }
+    Ok(())
+}
+
 #[post("/tracks", data = "<track>")]
 fn post_track(mut track: Json<Track>) -> Result<Json<TrackCreateResult>, ApiError> {
+    check_playable(&track)?;
+    let id = Uuid::new_v4();
+    track.id = id.to_string();
+    let client = redis::Client::open("redis://127.0.0.1/")?;
+    let con = client.get_connection()?;
+    let serialized = track.0.to_document()?;
+    con.hset("tracks", &id.to_string(), serialized)?;
+    con.hset("stats", &id.to_string(), serde_json::to_string(&track.stats())?)?;
+    Result::Ok(Json(TrackCreateResult::Created(id.to_string(), Box::new(track.0))))
+}
+
+#[post("/tracks/text", data = "<content>")]
+fn import_track(content: String) -> Result<Json<TrackCreateResult>, ApiError> {
+    let mut track = match Track::parse(&content) {
+        Ok(track) => track,
+        Err(errors) => return Result::Ok(Json(TrackCreateResult::Invalid(errors.iter().map(|e| e.to_string()).collect()))),
+    };
+    check_playable(&track)?;
     let id = Uuid::new_v4();
     track.id = id.to_string();
     let client = redis::Client::open("redis://127.0.0.1/")?;
     let con = client.get_connection()?;
-    let serialized = serde_json::to_string(&track.0)?;
+    let serialized = track.to_document()?;
     con.hset("tracks", &id.to_string(), serialized)?;
-    Result::Ok(Json(TrackCreateResult::Created(id.to_string(), track.0)))
+    con.hset("stats", &id.to_string(), serde_json::to_string(&track.stats())?)?;
+    Result::Ok(Json(TrackCreateResult::Created(id.to_string(), Box::new(track))))
+}
+
+/// seed the lyrics of a stored track from an lrc file, into the arrangement at index
+/// `arrangement` or every arrangement when none is given
+#[post("/tracks/<id>/lyrics?<arrangement>", data = "<content>")]
+fn import_lyrics(id: String, arrangement: Option<usize>, content: String) -> Result<Json<TrackUpdateResult>, ApiError> {
+    let lrc = match parse_lrc(&content) {
+        Ok(lrc) => lrc,
+        Err(error) => return Result::Ok(Json(TrackUpdateResult::Invalid(vec![error.to_string()]))),
+    };
+    let client = redis::Client::open("redis://127.0.0.1/")?;
+    let con = client.get_connection()?;
+    let result: String = con.hget("tracks", &id.to_string())?;
+    let mut track = Track::from_document(&result)?;
+    match arrangement {
+        Some(index) => match track.arrangements.get_mut(index) {
+            Some(arrangement) => arrangement.data.lyrics = lrc.lyrics,
+            None => return Result::Ok(Json(TrackUpdateResult::Invalid(vec![format!("no arrangement {}", index)]))),
+        },
+        None => {
+            for arrangement in &mut track.arrangements {
+                arrangement.data.lyrics = lrc.lyrics.clone();
+            }
+        }
+    }
+    con.hset("tracks", &id.to_string(), track.to_document()?)?;
+    Result::Ok(Json(TrackUpdateResult::Updated(Box::new(track))))
+}
+
+/// measure search results are sorted by, easiest first unless it starts with `-`,
+/// unknown measures keep the order tracks are found in
+fn sort_order(sort: &str) -> Option<(fn(&TrackStats) -> f64, bool)> {
+    let (name, descending) = if sort.starts_with('-') { (&sort[1..], true) } else { (sort, false) };
+    let key: fn(&TrackStats) -> f64 = match name {
+        "difficulty" => |s| s.difficulty,
+        "density" => |s| s.average_density,
+        _ => return None,
+    };
+    Some((key, descending))
 }
 
-#[get("/tracks?<term>")]
-fn search_track(term: String) -> Result<Json<SearchResponse>, ApiError> {
+#[get("/tracks?<term>&<sort>")]
+fn search_track(term: String, sort: Option<String>) -> Result<Json<SearchResponse>, ApiError> {
+    let order = sort.as_ref().and_then(|sort| sort_order(sort));
     let client = redis::Client::open("redis://127.0.0.1/")?;
     let con = client.get_connection()?;
+    let stored_stats: HashMap<String, String> = con.hgetall("stats")?;
     let mut items: Vec<SearchItem> = Vec::new();
     let results: redis::Iter<(String, String)> = con.hscan("tracks")?;
     for (id, result) in results {
-        let track: Track = serde_json::from_str(&result)?;
+        let track = Track::from_document(&result)?;
         if track.name.contains(&term) {
+            // tracks stored before stats were kept get them on the fly
+            let stats = stored_stats
+                .get(&id)
+                .and_then(|s| serde_json::from_str(s).ok())
+                .unwrap_or_else(|| track.stats());
             items.push(SearchItem {
                 name: track.name,
                 id,
                 youtube_id: track.youtube_id,
+                stats: Some(stats),
             })
         }
     }
+    if let Some((key, descending)) = order {
+        let value = |item: &SearchItem| item.stats.as_ref().map(key).unwrap_or(0.0);
+        items.sort_by(|a, b| value(a).partial_cmp(&value(b)).unwrap_or(::std::cmp::Ordering::Equal));
+        if descending {
+            items.reverse();
+        }
+    }
     Result::Ok(Json(SearchResponse::Result {
         term,
         items,
@@ -85,17 +177,47 @@ fn search_track(term: String) -> Result<Json<SearchResponse>, ApiError> {
     }))
 }
 
+/// the arrangements of a track to pick from, without their charts
 #[get("/tracks/<id>")]
-fn get_track(id: String) -> Result<Json<TrackLoadResult>, ApiError> {
+fn get_track(id: String) -> Result<Json<ArrangementsLoadResult>, ApiError> {
+    let client = redis::Client::open("redis://127.0.0.1/")?;
+    let con = client.get_connection()?;
+    let result: String = con.hget("tracks", &id.to_string())?;
+    let track = Track::from_document(&result)?;
+    Result::Ok(Json(ArrangementsLoadResult::Loaded(track.summaries())))
+}
+
+#[get("/tracks/<id>/arrangements/<index>")]
+fn get_arrangement(id: String, index: usize) -> Result<Json<TrackLoadResult>, ApiError> {
+    let client = redis::Client::open("redis://127.0.0.1/")?;
+    let con = client.get_connection()?;
+    let result: String = con.hget("tracks", &id.to_string())?;
+    let mut track = Track::from_document(&result)?;
+    if index >= track.arrangements.len() {
+        return Result::Ok(Json(TrackLoadResult::Error));
+    }
+    Result::Ok(Json(TrackLoadResult::Loaded(Box::new(track.arrangements.swap_remove(index)))))
+}
+
+#[get("/tracks/<id>/text")]
+fn get_track_text(id: String) -> Result<String, ApiError> {
     let client = redis::Client::open("redis://127.0.0.1/")?;
     let con = client.get_connection()?;
     let result: String = con.hget("tracks", &id.to_string())?;
-    let track: Track = serde_json::from_str(&result)?;
-    Result::Ok(Json(TrackLoadResult::Loaded(track.data)))
+    let track = Track::from_document(&result)?;
+    Result::Ok(track.to_string())
 }
 
 fn main() {
     let static_files = StaticFiles::from("./target/deploy");
-    let routes = routes![post_track, search_track, get_track];
+    let routes = routes![
+        post_track,
+        import_track,
+        import_lyrics,
+        search_track,
+        get_track,
+        get_arrangement,
+        get_track_text
+    ];
     rocket::ignite().mount("/", routes).mount("/", static_files).launch();
 }
@@ -0,0 +1,370 @@
+use crate::track::Action;
+use crate::track::Fret;
+use crate::track::HandPosition;
+use crate::track::Techniques;
+use crate::track::TrackData;
+use crate::track::HAND_SPAN;
+use crate::tuning::Tuning;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// cost of moving the hand at all, on top of `SHIFT_PER_FRET` for every fret it travels
+const SHIFT: u32 = 4;
+const SHIFT_PER_FRET: u32 = 2;
+/// for every fret the index finger sits below the lowest note
+const REACH: u32 = 2;
+/// for every fret a shape is wider than the hand
+const STRETCH: u32 = 8;
+/// for every string cut off while the previous note on it still rings
+const RINGING: u32 = 6;
+/// ways of playing the notes starting together considered at most
+const MAX_SHAPES: usize = 64;
+
+/// notes placed on strings and frets, with the hand positions to play them from
+#[derive(PartialEq, Clone, Debug)]
+pub struct Fingering {
+    pub actions: Vec<Action>,
+    pub hand_positions: Vec<HandPosition>,
+    /// notes no string of the instrument can play
+    pub unplayable: usize,
+}
+
+/// one action and the sets of frets it can be played with
+struct Slot {
+    options: Vec<Vec<Fret>>,
+}
+
+/// a pick of one option for every slot of a group, no two on the same string
+struct Shape {
+    picks: Vec<usize>,
+    /// string and end of every fret
+    strings: Vec<(u8, Duration)>,
+    /// index finger positions the shape can be played from, with the cost of each
+    anchors: Vec<(u8, u32)>,
+}
+
+/// every fret an index finger can rest on to play `frets`, open strings leave the hand alone
+fn anchors(frets: &[&Fret]) -> Vec<(u8, u32)> {
+    let fretted: Vec<u8> = frets.iter().map(|f| f.fret).filter(|f| *f > 0).collect();
+    let (low, high) = match (fretted.iter().min(), fretted.iter().max()) {
+        (Some(low), Some(high)) => (*low, *high),
+        _ => return vec![],
+    };
+    let width = high - low + 1;
+    if width > HAND_SPAN {
+        return vec![(low, u32::from(width - HAND_SPAN) * STRETCH)];
+    }
+    ((high + 1).saturating_sub(HAND_SPAN).max(1)..=low)
+        .map(|anchor| (anchor, u32::from(low - anchor) * REACH))
+        .collect()
+}
+
+fn shapes(slots: &[Slot]) -> Vec<Shape> {
+    fn pick(slots: &[Slot], picks: &mut Vec<usize>, used: &mut Vec<u8>, shapes: &mut Vec<Vec<usize>>) {
+        if shapes.len() >= MAX_SHAPES {
+            return;
+        }
+        let slot = match slots.get(picks.len()) {
+            Some(slot) => slot,
+            None => return shapes.push(picks.clone()),
+        };
+        for (index, option) in slot.options.iter().enumerate() {
+            if option.iter().any(|f| used.contains(&f.string)) {
+                continue;
+            }
+            picks.push(index);
+            used.extend(option.iter().map(|f| f.string));
+            pick(slots, picks, used, shapes);
+            used.truncate(used.len() - option.len());
+            picks.pop();
+        }
+    }
+    let mut picks = vec![];
+    pick(slots, &mut vec![], &mut vec![], &mut picks);
+    if picks.is_empty() {
+        // strings given by the chart can clash, play them as written
+        picks.push(vec![0; slots.len()]);
+    }
+    picks
+        .into_iter()
+        .map(|picks| {
+            let frets: Vec<&Fret> = slots.iter().zip(&picks).flat_map(|(s, p)| s.options[*p].iter()).collect();
+            Shape {
+                strings: frets.iter().map(|f| (f.string, f.ends_at)).collect(),
+                anchors: anchors(&frets),
+                picks,
+            }
+        })
+        .collect()
+}
+
+/// shape and anchor of a group, the total cost of getting there and the state of the group before
+type State = ((usize, Option<u8>), u32, usize);
+
+fn shift(from: Option<u8>, to: u8) -> u32 {
+    match from {
+        None => u32::from(to),
+        Some(from) if from == to => 0,
+        Some(from) => SHIFT + u32::from(from.max(to) - from.min(to)) * SHIFT_PER_FRET,
+    }
+}
+
+/// pick an option for every slot of every group of slots starting at the same time, and the hand
+/// position each group is played from, so that the hand moves and stretches as little as possible
+fn solve(groups: &[(Duration, Vec<Slot>)]) -> (Vec<Vec<usize>>, Vec<Option<u8>>) {
+    let shapes: Vec<Vec<Shape>> = groups.iter().map(|(_, slots)| shapes(slots)).collect();
+    let mut states: Vec<Vec<State>> = vec![];
+    for (index, (starts_at, _)) in groups.iter().enumerate() {
+        let start = vec![((0, None), 0, 0)];
+        let previous = states.last().unwrap_or(&start);
+        let mut next: BTreeMap<(usize, Option<u8>), (u32, usize)> = BTreeMap::new();
+        for (back, ((last_shape, hand), cost, _)) in previous.iter().enumerate() {
+            let ringing = match index {
+                0 => vec![],
+                _ => shapes[index - 1][*last_shape]
+                    .strings
+                    .iter()
+                    .filter(|(_, ends_at)| ends_at > starts_at)
+                    .map(|(string, _)| *string)
+                    .collect(),
+            };
+            for (shape_index, shape) in shapes[index].iter().enumerate() {
+                let cut = shape.strings.iter().filter(|(s, _)| ringing.contains(s)).count() as u32 * RINGING;
+                let moves: Vec<(Option<u8>, u32)> = if shape.anchors.is_empty() {
+                    vec![(*hand, 0)]
+                } else {
+                    shape.anchors.iter().map(|(a, c)| (Some(*a), c + shift(*hand, *a))).collect()
+                };
+                for (anchor, extra) in moves {
+                    let total = cost + cut + extra;
+                    let entry = next.entry((shape_index, anchor)).or_insert((total, back));
+                    if total < entry.0 {
+                        *entry = (total, back);
+                    }
+                }
+            }
+        }
+        states.push(next.into_iter().map(|(key, (cost, back))| (key, cost, back)).collect());
+    }
+
+    let mut picks = vec![];
+    let mut hands = vec![];
+    let mut state = states.last().and_then(|last| (0..last.len()).min_by_key(|i| last[*i].1)).unwrap_or(0);
+    for (index, group) in states.iter().enumerate().rev() {
+        let ((shape, hand), _, back) = group[state];
+        picks.push(shapes[index][shape].picks.clone());
+        hands.push(hand);
+        state = back;
+    }
+    picks.reverse();
+    hands.reverse();
+    (picks, hands)
+}
+
+/// a hand position wherever the anchor of a group changes
+fn hand_positions(groups: &[(Duration, Vec<Slot>)], hands: &[Option<u8>]) -> Vec<HandPosition> {
+    let mut positions: Vec<HandPosition> = vec![];
+    for ((at, _), hand) in groups.iter().zip(hands) {
+        if let Some(fret) = hand {
+            if positions.last().map(|p| p.fret != *fret).unwrap_or(true) {
+                positions.push(HandPosition { fret: *fret, at: *at });
+            }
+        }
+    }
+    positions
+}
+
+/// every fret playing `pitch` on an instrument tuned to `tuning` with `frets` frets
+fn positions(pitch: u8, tuning: &Tuning, frets: u8, note: &Fret) -> Vec<Vec<Fret>> {
+    let frets = frets.saturating_sub(tuning.capo);
+    (1..=tuning.strings.len() as u8)
+        .filter_map(|string| {
+            let fret = tuning.fret_for(string, pitch).filter(|f| *f <= frets)?;
+            Some(vec![Fret {
+                fret,
+                string,
+                ..note.clone()
+            }])
+        })
+        .collect()
+}
+
+fn group<T>(items: Vec<(Duration, T)>) -> Vec<(Duration, Vec<T>)> {
+    let mut groups: Vec<(Duration, Vec<T>)> = vec![];
+    for (at, item) in items {
+        match groups.last_mut() {
+            Some((last, group)) if *last == at => group.push(item),
+            _ => groups.push((at, vec![item])),
+        }
+    }
+    groups
+}
+
+/// place notes given as start, end and midi pitch on the strings of `tuning`,
+/// notes starting together never share a string
+pub fn finger_notes(notes: &[(Duration, Duration, u8)], tuning: &Tuning, frets: u8) -> Fingering {
+    let mut notes = notes.to_vec();
+    notes.sort_by(|a, b| a.0.cmp(&b.0).then(b.2.cmp(&a.2)));
+    let mut unplayable = 0;
+    let slots = notes
+        .iter()
+        .filter_map(|(starts_at, ends_at, pitch)| {
+            let note = Fret {
+                fret: 0,
+                string: 0,
+                starts_at: *starts_at,
+                ends_at: *ends_at,
+                techniques: Techniques::default(),
+            };
+            let options = positions(*pitch, tuning, frets, &note);
+            if options.is_empty() {
+                unplayable += 1;
+                return None;
+            }
+            Some((*starts_at, Slot { options }))
+        })
+        .collect();
+    let groups = group(slots);
+    let (picks, hands) = solve(&groups);
+    let actions = groups
+        .iter()
+        .zip(&picks)
+        .flat_map(|((_, slots), picks)| slots.iter().zip(picks).map(|(s, p)| Action::Fret(s.options[*p][0].clone())))
+        .collect();
+    Fingering {
+        actions,
+        hand_positions: hand_positions(&groups, &hands),
+        unplayable,
+    }
+}
+
+/// hand positions playing `actions` as written with the least movement
+pub fn plan_hand(actions: &[Action]) -> Vec<HandPosition> {
+    let mut sorted: Vec<&Action> = actions.iter().collect();
+    sorted.sort_by_key(|a| *a.starts_at());
+    let groups = group(
+        sorted
+            .into_iter()
+            .map(|a| {
+                let options = vec![a.frets().into_iter().cloned().collect()];
+                (*a.starts_at(), Slot { options })
+            })
+            .collect(),
+    );
+    let (_, hands) = solve(&groups);
+    hand_positions(&groups, &hands)
+}
+
+impl TrackData {
+    /// move single notes to the strings that keep the hand still the most and plan the hand
+    /// positions again, for the full chart and every level, other actions stay where they are
+    pub fn refinger(&mut self, tuning: &Tuning, frets: u8) {
+        fn refinger(actions: &mut [Action], tuning: &Tuning, frets: u8) -> Vec<HandPosition> {
+            actions.sort_by_key(|a| *a.starts_at());
+            let slots = actions
+                .iter()
+                .map(|action| {
+                    let written = vec![action.frets().into_iter().cloned().collect()];
+                    let options = match action {
+                        Action::Fret(f) => tuning
+                            .pitch_of(f)
+                            .map(|pitch| positions(pitch, tuning, frets, f))
+                            .filter(|options| !options.is_empty())
+                            .unwrap_or(written),
+                        _ => written,
+                    };
+                    (*action.starts_at(), Slot { options })
+                })
+                .collect();
+            let groups = group(slots);
+            let (picks, hands) = solve(&groups);
+            let placed = groups
+                .iter()
+                .zip(&picks)
+                .flat_map(|((_, slots), picks)| slots.iter().zip(picks).map(|(s, p)| s.options[*p][0].clone()).collect::<Vec<Fret>>());
+            for (action, fret) in actions.iter_mut().zip(placed) {
+                if let Action::Fret(f) = action {
+                    *f = fret;
+                }
+            }
+            hand_positions(&groups, &hands)
+        }
+        self.hand_positions = refinger(&mut self.actions, tuning, frets);
+        for level in self.phrases.iter_mut().flat_map(|p| p.levels.iter_mut()) {
+            level.hand_positions = refinger(&mut level.actions, tuning, frets);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::fret_action;
+    use crate::track::hand_position;
+    use crate::track::track_data;
+
+    fn note(starts_at: u64, ends_at: u64, pitch: u8) -> (Duration, Duration, u8) {
+        (Duration::from_millis(starts_at), Duration::from_millis(ends_at), pitch)
+    }
+
+    #[test]
+    fn test_finger_notes() {
+        // a c major scale stays in first position, then a note only the low e string has
+        // and one below the instrument
+        let notes = vec![
+            note(0, 250, 60),
+            note(250, 500, 62),
+            note(500, 750, 64),
+            note(750, 1000, 65),
+            note(1000, 1250, 67),
+            note(1250, 1500, 41),
+            note(1500, 2000, 20),
+        ];
+        let fingering = finger_notes(&notes, &Tuning::standard(), 24);
+        assert_eq!(1, fingering.unplayable);
+        assert_eq!(
+            vec![
+                fret_action(0, 250, 1, 2),
+                fret_action(250, 500, 3, 2),
+                fret_action(500, 750, 0, 1),
+                fret_action(750, 1000, 1, 1),
+                fret_action(1000, 1250, 3, 1),
+                fret_action(1250, 1500, 1, 6),
+            ],
+            fingering.actions
+        );
+        assert_eq!(vec![hand_position(0, 1)], fingering.hand_positions);
+        // a run too high for first position crosses strings instead of shifting along one
+        let notes = vec![note(0, 250, 76), note(250, 500, 79), note(500, 750, 83), note(750, 1000, 86)];
+        let fingering = finger_notes(&notes, &Tuning::standard(), 24);
+        let positions: Vec<(u8, u8)> = fingering.actions.iter().map(|a| (a.frets()[0].string, a.frets()[0].fret)).collect();
+        assert_eq!(vec![(3, 21), (2, 20), (1, 19), (1, 22)], positions);
+        assert_eq!(vec![hand_position(0, 19)], fingering.hand_positions);
+    }
+
+    #[test]
+    fn test_plan_hand() {
+        // following the hand note by note starts at 5 and shifts down for the 3,
+        // starting at 3 covers all of them
+        let actions = vec![fret_action(0, 500, 5, 5), fret_action(500, 1000, 3, 5), fret_action(1000, 1500, 6, 4)];
+        assert_eq!(vec![hand_position(0, 3)], plan_hand(&actions));
+        // open strings keep the hand where it is
+        let actions = vec![fret_action(0, 500, 0, 1), fret_action(500, 1000, 7, 1), fret_action(1000, 1500, 0, 6)];
+        assert_eq!(vec![hand_position(500, 7)], plan_hand(&actions));
+    }
+
+    #[test]
+    fn test_refinger() {
+        // the b on the fourteenth fret of the a string is the open b string
+        let mut data = track_data(
+            vec![fret_action(0, 500, 5, 3), fret_action(500, 1000, 14, 5), fret_action(1000, 1500, 0, 1)],
+            vec![],
+        );
+        data.refinger(&Tuning::standard(), 24);
+        assert_eq!(
+            vec![fret_action(0, 500, 1, 2), fret_action(500, 1000, 0, 2), fret_action(1000, 1500, 0, 1)],
+            data.actions
+        );
+        assert_eq!(vec![hand_position(0, 1)], data.hand_positions);
+    }
+}
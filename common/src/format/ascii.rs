@@ -0,0 +1,557 @@
+use crate::format::chart::bend_interval;
+use crate::format::chart::ChartBuilder;
+use crate::tempo::TempoChange;
+use crate::tempo::TempoMap;
+use crate::tempo::TimeSignature;
+use crate::track::Action;
+use crate::track::Fret;
+use crate::track::Interval;
+use crate::track::Techniques;
+use crate::track::TrackData;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+/// every dash in a tab line is a sixteenth note
+pub const COLUMNS_PER_BEAT: u32 = 4;
+/// staves with fewer or more strings are not guitar or bass tab
+const MIN_STRINGS: usize = 4;
+const MAX_STRINGS: usize = 7;
+const MAX_FRET: u8 = 24;
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct UnparsedLine {
+    /// starting at 1
+    pub line: usize,
+    pub content: String,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct AsciiTab {
+    pub data: TrackData,
+    /// tab lines that were skipped, either whole staves with an odd number of strings
+    /// or single lines with characters that are not tab notation
+    pub unparsed: Vec<UnparsedLine>,
+}
+
+fn is_notation(c: char) -> bool {
+    c.is_ascii_digit() || "-|/\\shpbr~xX()".contains(c)
+}
+
+/// a tab line is an optional string name, a bar and a run of dashes or notes, returns
+/// the content after the bar
+fn tab_body(line: &str) -> Option<&str> {
+    let (name, body) = line.trim().split_once('|')?;
+    let name = name.trim();
+    let named = name.len() <= 3 && name.chars().all(|c| c.is_ascii_alphabetic() || c == '#');
+    let dashes = body.chars().filter(|c| *c == '-').count() >= 4;
+    if named && (dashes || (body.len() >= 4 && body.chars().all(is_notation))) {
+        Some(body)
+    } else {
+        None
+    }
+}
+
+struct TabNote {
+    column: usize,
+    string: u8,
+    fret: u8,
+    bend: Option<Interval>,
+    slide: bool,
+    techniques: Techniques,
+}
+
+#[derive(PartialEq)]
+enum After {
+    Nothing,
+    Bend,
+    Release,
+    HammerOn,
+    PullOff,
+}
+
+/// read the notes of a single string, `None` if the line contains something else
+fn parse_string(body: &[char], string: u8) -> Option<Vec<TabNote>> {
+    let mut notes: Vec<TabNote> = vec![];
+    let mut after = After::Nothing;
+    let mut column = 0;
+    while column < body.len() {
+        let c = body[column];
+        if let Some(digit) = c.to_digit(10) {
+            let mut value = digit as u8;
+            let start = column;
+            if let Some(next) = body.get(column + 1).and_then(|c| c.to_digit(10)) {
+                if value * 10 + (next as u8) <= MAX_FRET {
+                    value = value * 10 + next as u8;
+                    column += 1;
+                }
+            }
+            match after {
+                After::Bend => {
+                    let note = notes.last_mut()?;
+                    let semitones = f64::from(value) - f64::from(note.fret);
+                    note.bend = Some(bend_interval(semitones.max(1.0)));
+                }
+                After::Release => (),
+                _ => notes.push(TabNote {
+                    column: start,
+                    string,
+                    fret: value,
+                    bend: None,
+                    slide: false,
+                    techniques: Techniques {
+                        hammer_on: after == After::HammerOn,
+                        pull_off: after == After::PullOff,
+                        ..Techniques::default()
+                    },
+                }),
+            }
+            after = After::Nothing;
+        } else {
+            match c {
+                '/' | '\\' | 's' => {
+                    if let Some(note) = notes.last_mut() {
+                        note.slide = true;
+                    }
+                }
+                'b' => {
+                    let note = notes.last_mut()?;
+                    note.bend = Some(Interval::Step);
+                    after = After::Bend;
+                }
+                'r' => after = After::Release,
+                'h' => after = After::HammerOn,
+                'p' => after = After::PullOff,
+                '~' => {
+                    if let Some(note) = notes.last_mut() {
+                        note.techniques.vibrato = true;
+                    }
+                    after = After::Nothing;
+                }
+                'x' | 'X' => {
+                    notes.push(TabNote {
+                        column,
+                        string,
+                        fret: 0,
+                        bend: None,
+                        slide: false,
+                        techniques: Techniques {
+                            dead: true,
+                            ..Techniques::default()
+                        },
+                    });
+                    after = After::Nothing;
+                }
+                // brackets around bend targets keep the state
+                '(' | ')' => (),
+                '-' | '|' | ' ' => after = After::Nothing,
+                _ => return None,
+            }
+        }
+        column += 1;
+    }
+    Some(notes)
+}
+
+/// parse plain text tab, the time of a note comes from its column with every column
+/// lasting a sixteenth note at `bpm`, a note rings until the next note on its string
+/// or the end of its staff
+pub fn parse_tab(content: &str, bpm: f64) -> AsciiTab {
+    let column_length = 60_000.0 / bpm.max(1.0) / f64::from(COLUMNS_PER_BEAT);
+    let millis = |index: usize| Duration::from_millis((index as f64 * column_length).round() as u64);
+    let lines: Vec<&str> = content.lines().collect();
+    let mut unparsed = vec![];
+    let mut chart = ChartBuilder::default();
+    let mut elapsed = 0;
+    let mut index = 0;
+    while index < lines.len() {
+        if tab_body(lines[index]).is_none() {
+            index += 1;
+            continue;
+        }
+        let first = index;
+        while index < lines.len() && tab_body(lines[index]).is_some() {
+            index += 1;
+        }
+        let staff = &lines[first..index];
+        let report = |offset: usize| UnparsedLine {
+            line: first + offset + 1,
+            content: staff[offset].to_string(),
+        };
+        if staff.len() < MIN_STRINGS || staff.len() > MAX_STRINGS {
+            unparsed.extend((0..staff.len()).map(report));
+            continue;
+        }
+        let bodies: Vec<Vec<char>> = staff.iter().filter_map(|l| tab_body(l)).map(|b| b.chars().collect()).collect();
+        let width = bodies.iter().map(|b| b.len()).max().unwrap_or(0);
+        // bar lines through the whole staff take no time
+        let mut time_of = vec![0; width + 1];
+        let mut time = 0;
+        for (column, slot) in time_of.iter_mut().take(width).enumerate() {
+            *slot = time;
+            if !bodies.iter().all(|b| b.get(column).map(|c| *c == '|').unwrap_or(true)) {
+                time += 1;
+            }
+        }
+        time_of[width] = time;
+
+        let mut notes = vec![];
+        for (offset, body) in bodies.iter().enumerate() {
+            match parse_string(body, offset as u8 + 1) {
+                Some(string_notes) => notes.extend(string_notes),
+                None => unparsed.push(report(offset)),
+            }
+        }
+        notes.sort_by_key(|n| (n.column, n.string));
+        for (position, note) in notes.iter().enumerate() {
+            let end = notes[position + 1..]
+                .iter()
+                .find(|n| n.string == note.string)
+                .map(|n| n.column)
+                .unwrap_or(width);
+            let fret = Fret {
+                fret: note.fret,
+                string: note.string,
+                starts_at: millis(elapsed + time_of[note.column]),
+                ends_at: millis(elapsed + time_of[end]),
+                techniques: note.techniques.clone(),
+            };
+            chart.note(fret, note.bend.clone(), note.slide);
+        }
+        elapsed += time;
+    }
+    AsciiTab {
+        data: chart.build(TempoMap::from_quarters(vec![(0.0, bpm)], vec![])),
+        unparsed,
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct TabOptions {
+    /// names written in front of every staff line, highest string first
+    pub strings: Vec<String>,
+    /// tempo the columns are laid out in instead of the chart's tempo map, measure
+    /// bars are only drawn when either is known
+    pub bpm: Option<f64>,
+    /// measure length while `bpm` overrides the tempo map
+    pub beats_per_measure: u32,
+    /// characters per staff line before wrapping to the next staff
+    pub width: usize,
+}
+
+impl Default for TabOptions {
+    fn default() -> Self {
+        TabOptions {
+            strings: ["e", "B", "G", "D", "A", "E"].iter().map(|s| s.to_string()).collect(),
+            bpm: None,
+            beats_per_measure: 4,
+            width: 80,
+        }
+    }
+}
+
+/// dead notes are an `x`, vibrato follows the fret as `~`
+fn token(fret: &Fret) -> String {
+    if fret.techniques.dead {
+        "x".to_string()
+    } else if fret.techniques.vibrato {
+        format!("{}~", fret.fret)
+    } else {
+        fret.fret.to_string()
+    }
+}
+
+/// lay out `data` as plain text tab, every column is a sixteenth note at the tempo in
+/// `options` or in the chart's tempo map, notes wider than a column push later notes
+/// on their string to the right
+pub fn write_tab<W: fmt::Write>(out: &mut W, data: &TrackData, options: &TabOptions) -> fmt::Result {
+    let tempo = match options.bpm {
+        Some(bpm) => TempoMap {
+            tempos: vec![TempoChange {
+                at: Duration::from_millis(0),
+                bpm: bpm.max(1.0),
+            }],
+            time_signatures: vec![TimeSignature {
+                measure: 0,
+                numerator: options.beats_per_measure.clamp(1, u32::from(u8::MAX)) as u8,
+                denominator: 4,
+            }],
+        },
+        None => data.tempo.clone(),
+    };
+    let barred = options.bpm.is_some() || !data.tempo.tempos.is_empty();
+    let slot = |at: &Duration| (tempo.quarter_at(*at) * f64::from(COLUMNS_PER_BEAT)).round().max(0.0) as usize;
+    let strings = options.strings.len();
+    let mut tokens: HashMap<(usize, usize), String> = HashMap::new();
+    let mut slots = 0;
+    for action in &data.actions {
+        let mut place = |fret: &Fret, token: String| {
+            let string = usize::from(fret.string);
+            if string >= 1 && string <= strings {
+                tokens.entry((string - 1, slot(&fret.starts_at))).or_insert(token);
+            }
+        };
+        match action {
+            Action::Fret(f) => place(f, token(f)),
+            Action::Bend(f, interval) => place(f, format!("{}b{}", f.fret, f.fret.saturating_add(interval.semitones()))),
+            Action::Chord(chord) => chord.frets.iter().for_each(|f| place(f, token(f))),
+            Action::Slide(from, to) => {
+                let direction = if to.fret < from.fret { '\\' } else { '/' };
+                place(from, format!("{}{}", from.fret, direction));
+                place(to, to.fret.to_string());
+            }
+        }
+        slots = slots.max(slot(action.ends_at())).max(slot(action.starts_at()) + 1);
+    }
+    if slots == 0 {
+        return Ok(());
+    }
+    // slots starting a measure, the last measure is filled up to its end
+    let mut measure_starts = vec![];
+    if barred {
+        for measure in 1.. {
+            let start = slot(&tempo.time_of_measure(measure));
+            if start >= slots {
+                slots = start;
+                break;
+            }
+            measure_starts.push(start);
+        }
+    }
+
+    // character position of every token, and where a staff may be cut as the end
+    // of one staff and the start of the next
+    let mut positions: Vec<(usize, usize, usize)> = vec![];
+    let mut bars = vec![];
+    let mut cuts = vec![];
+    let mut free = vec![0; strings];
+    let mut cursor = 0;
+    for slot in 0..slots {
+        let busy = free.iter().cloned().max().unwrap_or(0);
+        if measure_starts.binary_search(&slot).is_ok() {
+            let bar = cursor.max(busy);
+            bars.push(bar);
+            cuts.push((bar, bar + 1));
+            cursor = bar + 1;
+        } else if !barred && slot > 0 && busy <= cursor {
+            cuts.push((cursor, cursor));
+        }
+        let at = (0..strings)
+            .filter(|string| tokens.contains_key(&(*string, slot)))
+            .map(|string| free[string])
+            .fold(cursor, usize::max);
+        for (string, free) in free.iter_mut().enumerate() {
+            if let Some(token) = tokens.get(&(string, slot)) {
+                positions.push((string, slot, at));
+                *free = at + token.len();
+            }
+        }
+        cursor = at + 1;
+    }
+    let end = free.iter().cloned().fold(cursor, usize::max);
+    cuts.push((end, end));
+
+    let mut rows = vec![vec!['-'; end]; strings];
+    for (string, slot, at) in positions {
+        for (offset, c) in tokens[&(string, slot)].chars().enumerate() {
+            rows[string][at + offset] = c;
+        }
+    }
+    for bar in bars {
+        for row in rows.iter_mut() {
+            row[bar] = '|';
+        }
+    }
+
+    let name_width = options.strings.iter().map(|s| s.len()).max().unwrap_or(0);
+    let width = options.width.saturating_sub(name_width + 2);
+    let mut start = 0;
+    let mut first = true;
+    while start < end {
+        // the widest staff that fits, or the narrowest one if none does
+        let (stop, next) = cuts
+            .iter()
+            .filter(|(stop, _)| *stop > start)
+            .take_while(|(stop, _)| stop - start <= width)
+            .last()
+            .or_else(|| cuts.iter().find(|(stop, _)| *stop > start))
+            .cloned()
+            .unwrap_or((end, end));
+        if !first {
+            writeln!(out)?;
+        }
+        first = false;
+        for (name, row) in options.strings.iter().zip(&rows) {
+            let staff: String = row[start..stop].iter().collect();
+            writeln!(out, "{:<width$}|{}|", name, staff, width = name_width)?;
+        }
+        start = next;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::fret;
+    use crate::track::fret_action;
+    use crate::track::hand_position;
+    use crate::track::track_data;
+    use crate::track::Action;
+
+    fn with(mut fret: Fret, technique: impl Fn(&mut Techniques)) -> Fret {
+        technique(&mut fret.techniques);
+        fret
+    }
+
+    #[test]
+    fn test_parse_tab() {
+        let content = "Intro
+
+e|--------|--0-----|
+B|--------|--1-----|
+G|5-7~----|--------|
+D|----5/7-|--------|
+A|x-------|--------|
+E|--------|--------|
+
+some lyrics | with a bar
+G|7b9-----|
+D|--------|
+A|--------|
+E|12h14p12|
+";
+        let tab = parse_tab(content, 150.0);
+        assert_eq!(Vec::<UnparsedLine>::new(), tab.unparsed);
+        assert_eq!(
+            vec![
+                fret_action(0, 200, 5, 3),
+                Action::Fret(with(fret(0, 1600, 0, 5), |t| t.dead = true)),
+                Action::Fret(with(fret(200, 1600, 7, 3), |t| t.vibrato = true)),
+                Action::Slide(fret(400, 600, 5, 4), fret(600, 1600, 7, 4)),
+                fret_action(1000, 1600, 0, 1),
+                fret_action(1000, 1600, 1, 2),
+                Action::Bend(fret(1600, 2400, 7, 1), Interval::Step),
+                fret_action(1600, 1900, 12, 4),
+                Action::Fret(with(fret(1900, 2200, 14, 4), |t| t.hammer_on = true)),
+                Action::Fret(with(fret(2200, 2400, 12, 4), |t| t.pull_off = true)),
+            ],
+            tab.data.actions
+        );
+        assert_eq!(
+            vec![
+                hand_position(0, 5),
+                hand_position(1000, 1),
+                hand_position(1600, 7),
+                hand_position(1900, 12)
+            ],
+            tab.data.hand_positions
+        );
+    }
+
+    #[test]
+    fn test_write_tab() {
+        let data = track_data(
+            vec![
+                fret_action(0, 250, 3, 6),
+                Action::Slide(fret(250, 500, 5, 5), fret(500, 1000, 7, 5)),
+                Action::Bend(fret(1000, 2000, 12, 3), Interval::Step),
+                fret_action(2000, 2500, 0, 1),
+                fret_action(2000, 2500, 1, 2),
+            ],
+            vec![],
+        );
+        let options = TabOptions {
+            strings: ["e", "B", "G", "D", "A", "E"].iter().map(|s| s.to_string()).collect(),
+            bpm: Some(120.0),
+            beats_per_measure: 2,
+            width: 20,
+        };
+        let mut out = String::new();
+        write_tab(&mut out, &data, &options).unwrap();
+        let expected = "\
+e|--------|--------|
+B|--------|--------|
+G|--------|12b14---|
+D|--------|--------|
+A|--5/7---|--------|
+E|3-------|--------|
+
+e|0-------|
+B|1-------|
+G|--------|
+D|--------|
+A|--------|
+E|--------|
+";
+        assert_eq!(expected, out);
+        let options = TabOptions {
+            bpm: None,
+            width: 30,
+            ..options
+        };
+        let mut out = String::new();
+        write_tab(&mut out, &data, &options).unwrap();
+        assert!(out.starts_with("e|----------------0---|\nB|----------------1---|\nG|--------12b14-------|\n"));
+    }
+
+    #[test]
+    fn test_write_tab_tempo_map() {
+        let data = TrackData {
+            actions: vec![fret_action(0, 500, 3, 6), fret_action(1500, 2500, 5, 5), fret_action(3500, 4500, 7, 4)],
+            tempo: TempoMap {
+                tempos: vec![
+                    TempoChange {
+                        at: Duration::from_millis(0),
+                        bpm: 120.0,
+                    },
+                    TempoChange {
+                        at: Duration::from_millis(1500),
+                        bpm: 60.0,
+                    },
+                ],
+                time_signatures: vec![TimeSignature {
+                    measure: 0,
+                    numerator: 3,
+                    denominator: 4,
+                }],
+            },
+            ..TrackData::default()
+        };
+        let mut out = String::new();
+        write_tab(&mut out, &data, &TabOptions::default()).unwrap();
+        let expected = "\
+e|------------|------------|
+B|------------|------------|
+G|------------|------------|
+D|------------|--------7---|
+A|------------|5-----------|
+E|3-----------|------------|
+";
+        assert_eq!(expected, out);
+    }
+
+    #[test]
+    fn test_tab_round_trip() {
+        let content =
+            "e|--------|0-------|\nB|--------|1-------|\nG|5-7-----|--------|\nD|----5/7-|--------|\nA|--------|--------|\nE|--------|--------|\n";
+        let tab = parse_tab(content, 150.0);
+        let options = TabOptions {
+            bpm: Some(150.0),
+            beats_per_measure: 2,
+            ..TabOptions::default()
+        };
+        let mut out = String::new();
+        write_tab(&mut out, &tab.data, &options).unwrap();
+        assert_eq!(content, out);
+    }
+
+    #[test]
+    fn test_parse_tab_unparsed() {
+        let content = "e|--0--|\nB|--1--|\nG|--2--|\n\ne|--0--|\nB|--1--|\nG|--2--|\nD|--2--|\nA|--0--|\nE|-?---|\n";
+        let tab = parse_tab(content, 120.0);
+        let lines: Vec<usize> = tab.unparsed.iter().map(|l| l.line).collect();
+        assert_eq!(vec![1, 2, 3, 10], lines);
+        assert_eq!(5, tab.data.actions.len());
+    }
+}
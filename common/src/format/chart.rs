@@ -0,0 +1,176 @@
+use crate::fingering::plan_hand;
+use crate::tempo::TempoMap;
+use crate::tempo::TimeSignature;
+use crate::track::Action;
+use crate::track::Chord;
+use crate::track::Fret;
+use crate::track::Interval;
+use crate::track::TrackData;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// repeat marks of a single measure
+#[derive(Clone, Default)]
+pub(crate) struct Repeat {
+    pub open: bool,
+    /// how many times to jump back to the repeat start
+    pub close: u8,
+    /// bit mask of the repeat passes this measure is played in, 0 for all
+    pub alternative: u8,
+}
+
+/// expand repeats and alternative endings into the order measures are played in
+pub(crate) fn playback_order(repeats: &[Repeat]) -> Vec<usize> {
+    let mut order = vec![];
+    let mut repeat_start = 0;
+    let mut pass: u8 = 0;
+    let mut remaining: HashMap<usize, u8> = HashMap::new();
+    let mut index = 0;
+    while index < repeats.len() {
+        let repeat = &repeats[index];
+        if repeat.open && index != repeat_start {
+            repeat_start = index;
+            pass = 0;
+        }
+        let alternative = repeat.alternative;
+        if alternative != 0 && pass < 8 && alternative & (1 << pass) == 0 {
+            index += 1;
+            continue;
+        }
+        order.push(index);
+        if repeat.close > 0 {
+            let left = remaining.entry(index).or_insert(repeat.close);
+            if *left > 0 {
+                *left -= 1;
+                pass += 1;
+                index = repeat_start;
+                continue;
+            }
+            remaining.remove(&index);
+            pass = 0;
+            repeat_start = index + 1;
+        }
+        index += 1;
+    }
+    order
+}
+
+/// time signatures of measures in playing order, written only where they change
+pub(crate) fn time_signatures<I: IntoIterator<Item = (u8, u8)>>(measures: I) -> Vec<TimeSignature> {
+    let mut signatures: Vec<TimeSignature> = vec![];
+    for (measure, (numerator, denominator)) in measures.into_iter().enumerate() {
+        let changed = signatures
+            .last()
+            .map(|s| s.numerator != numerator || s.denominator != denominator)
+            .unwrap_or(true);
+        if changed {
+            signatures.push(TimeSignature {
+                measure: measure as u32,
+                numerator,
+                denominator,
+            });
+        }
+    }
+    signatures
+}
+
+/// collects notes in playing order, joining tied notes and slides into single actions
+#[derive(Default)]
+pub(crate) struct ChartBuilder {
+    actions: Vec<Action>,
+    last: HashMap<u8, usize>,
+    sliding: HashMap<u8, usize>,
+}
+
+impl ChartBuilder {
+    /// add a note, `slide` connects it to the next note played on the same string
+    pub fn note(&mut self, fret: Fret, bend: Option<Interval>, slide: bool) {
+        let string = fret.string;
+        if let Some(index) = self.sliding.remove(&string) {
+            if let Action::Fret(from) = self.actions[index].clone() {
+                self.actions[index] = Action::Slide(from, fret);
+                self.last.insert(string, index);
+                return;
+            }
+        }
+        let action = match bend {
+            Some(interval) => Action::Bend(fret, interval),
+            None => {
+                if slide {
+                    self.sliding.insert(string, self.actions.len());
+                }
+                Action::Fret(fret)
+            }
+        };
+        self.last.insert(string, self.actions.len());
+        self.actions.push(action);
+    }
+
+    /// let the last note on `string` ring until `ends_at`
+    pub fn tie(&mut self, string: u8, ends_at: Duration) {
+        if let Some(index) = self.last.get(&string) {
+            match &mut self.actions[*index] {
+                Action::Fret(f) => f.ends_at = ends_at,
+                Action::Slide(_, f) => f.ends_at = ends_at,
+                Action::Bend(f, _) => f.ends_at = ends_at,
+                Action::Chord(c) => c.frets.iter_mut().filter(|f| f.string == string).for_each(|f| f.ends_at = ends_at),
+            }
+        }
+    }
+
+    /// add a chord, ties on its strings extend the chord's frets
+    pub fn chord(&mut self, chord: Chord) {
+        for fret in &chord.frets {
+            self.sliding.remove(&fret.string);
+            self.last.insert(fret.string, self.actions.len());
+        }
+        self.actions.push(Action::Chord(chord));
+    }
+
+    pub fn build(mut self, tempo: TempoMap) -> TrackData {
+        self.actions.sort_by_key(|a| *a.starts_at());
+        let hand_positions = plan_hand(&self.actions);
+        TrackData {
+            actions: self.actions,
+            hand_positions,
+            tempo,
+            ..TrackData::default()
+        }
+    }
+}
+
+/// closest bend interval for a bend of `semitones`
+pub(crate) fn bend_interval(semitones: f64) -> Interval {
+    if semitones <= 1.0 {
+        Interval::HalfStep
+    } else if semitones <= 2.0 {
+        Interval::Step
+    } else {
+        Interval::DoubleStep
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_playback_order() {
+        let repeat = |open: bool, close: u8, alternative: u8| Repeat { open, close, alternative };
+        let repeats = vec![
+            repeat(false, 0, 0),
+            repeat(true, 0, 0),
+            repeat(false, 1, 0b01),
+            repeat(false, 0, 0b10),
+            repeat(true, 2, 0),
+        ];
+        assert_eq!(vec![0, 1, 2, 1, 3, 4, 4, 4], playback_order(&repeats));
+    }
+
+    #[test]
+    fn test_time_signatures() {
+        let signatures = time_signatures(vec![(4, 4), (4, 4), (6, 8), (6, 8), (4, 4)]);
+        let measures: Vec<(u32, u8)> = signatures.iter().map(|s| (s.measure, s.numerator)).collect();
+        assert_eq!(vec![(0, 4), (2, 6), (4, 4)], measures);
+    }
+}
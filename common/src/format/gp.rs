@@ -0,0 +1,928 @@
+use crate::format::chart::bend_interval;
+use crate::format::chart::playback_order;
+use crate::format::chart::time_signatures;
+use crate::format::chart::ChartBuilder;
+use crate::format::chart::Repeat;
+use crate::tempo::TempoMap;
+use crate::track::Fret;
+use crate::track::Harmonic;
+use crate::track::Techniques;
+use crate::track::TrackData;
+use crate::tuning::Tuning;
+use std::collections::HashMap;
+use std::fmt;
+
+/// bend values are stored in hundredths of a tone
+const BEND_SEMITONE: f64 = 50.0;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum GpParseErrorKind {
+    UnsupportedVersion(String),
+    UnexpectedEnd,
+    InvalidValue(&'static str),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct GpParseError {
+    pub kind: GpParseErrorKind,
+    pub offset: usize,
+}
+
+impl fmt::Display for GpParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            GpParseErrorKind::UnsupportedVersion(version) => write!(f, "unsupported guitar pro version `{}`", version),
+            GpParseErrorKind::UnexpectedEnd => write!(f, "unexpected end of file at byte {}", self.offset),
+            GpParseErrorKind::InvalidValue(what) => write!(f, "invalid {} at byte {}", what, self.offset),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct GpTrack {
+    pub name: String,
+    pub tuning: Tuning,
+    pub frets: u8,
+    pub data: TrackData,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct GpSong {
+    pub title: String,
+    pub artist: String,
+    pub tempo: u32,
+    pub tracks: Vec<GpTrack>,
+}
+
+struct MeasureHeader {
+    numerator: u8,
+    denominator: u8,
+    repeat: Repeat,
+}
+
+impl MeasureHeader {
+    fn quarters(&self) -> f64 {
+        f64::from(self.numerator) * 4.0 / f64::from(self.denominator)
+    }
+}
+
+struct TrackHeader {
+    name: String,
+    percussion: bool,
+    tuning: Vec<u8>,
+    frets: u8,
+    capo: u8,
+}
+
+#[derive(PartialEq)]
+enum NoteKind {
+    Normal,
+    Tie,
+    Dead,
+}
+
+struct Note {
+    string: u8,
+    fret: u8,
+    kind: NoteKind,
+    bend: i32,
+    slide: bool,
+    /// hammer-on or pull-off to the next note on the string
+    legato: bool,
+    techniques: Techniques,
+}
+
+struct Beat {
+    /// quarter notes from the start of the measure
+    start: f64,
+    duration: f64,
+    notes: Vec<Note>,
+    tempo: Option<u32>,
+    /// tapping and vibrato of all notes of the beat
+    techniques: Techniques,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+    version: (u8, u8),
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, kind: GpParseErrorKind) -> GpParseError {
+        GpParseError { kind, offset: self.offset }
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], GpParseError> {
+        if self.offset + count > self.bytes.len() {
+            return Err(self.error(GpParseErrorKind::UnexpectedEnd));
+        }
+        let bytes = &self.bytes[self.offset..self.offset + count];
+        self.offset += count;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, count: usize) -> Result<(), GpParseError> {
+        self.take(count).map(|_| ())
+    }
+
+    fn u8(&mut self) -> Result<u8, GpParseError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn i8(&mut self) -> Result<i8, GpParseError> {
+        Ok(self.u8()? as i8)
+    }
+
+    fn bool(&mut self) -> Result<bool, GpParseError> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn i16(&mut self) -> Result<i16, GpParseError> {
+        let bytes = self.take(2)?;
+        Ok(i16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn i32(&mut self) -> Result<i32, GpParseError> {
+        let bytes = self.take(4)?;
+        Ok(i32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// guitar pro stores text in a single byte encoding, latin-1 is close enough
+    fn string(&mut self, length: usize) -> Result<String, GpParseError> {
+        Ok(self.take(length)?.iter().map(|b| char::from(*b)).collect())
+    }
+
+    /// length byte followed by a field of `size` bytes
+    fn byte_size_string(&mut self, size: usize) -> Result<String, GpParseError> {
+        let length = usize::from(self.u8()?);
+        let field = self.string(size)?;
+        Ok(field.chars().take(length).collect())
+    }
+
+    /// field size as int followed by a byte size string
+    fn int_byte_size_string(&mut self) -> Result<String, GpParseError> {
+        let size = self.i32()?;
+        if size < 1 {
+            return Err(self.error(GpParseErrorKind::InvalidValue("string size")));
+        }
+        self.byte_size_string(size as usize - 1)
+    }
+
+    fn int_size_string(&mut self) -> Result<String, GpParseError> {
+        let size = self.i32()?;
+        if size < 0 {
+            return Err(self.error(GpParseErrorKind::InvalidValue("string size")));
+        }
+        self.string(size as usize)
+    }
+
+    fn is_gp5(&self) -> bool {
+        self.version.0 >= 5
+    }
+
+    fn read_version(&mut self) -> Result<(), GpParseError> {
+        let version = self.byte_size_string(30)?;
+        self.version = match version.trim_start_matches("FICHIER GUITAR PRO ") {
+            "v3.00" => (3, 0),
+            "v4.00" => (4, 0),
+            "v4.06" => (4, 6),
+            "L4.06" => (4, 6),
+            "v5.00" => (5, 0),
+            "v5.10" => (5, 10),
+            _ => {
+                return Err(GpParseError {
+                    kind: GpParseErrorKind::UnsupportedVersion(version),
+                    offset: 0,
+                })
+            }
+        };
+        Ok(())
+    }
+
+    fn read_info(&mut self) -> Result<(String, String), GpParseError> {
+        let title = self.int_byte_size_string()?;
+        let _subtitle = self.int_byte_size_string()?;
+        let artist = self.int_byte_size_string()?;
+        let _album = self.int_byte_size_string()?;
+        let _words = self.int_byte_size_string()?;
+        if self.is_gp5() {
+            let _music = self.int_byte_size_string()?;
+        }
+        let _copyright = self.int_byte_size_string()?;
+        let _tab = self.int_byte_size_string()?;
+        let _instructions = self.int_byte_size_string()?;
+        let notice_lines = self.i32()?;
+        for _ in 0..notice_lines {
+            self.int_byte_size_string()?;
+        }
+        Ok((title, artist))
+    }
+
+    fn read_lyrics(&mut self) -> Result<(), GpParseError> {
+        let _track = self.i32()?;
+        for _ in 0..5 {
+            let _starting_measure = self.i32()?;
+            self.int_size_string()?;
+        }
+        Ok(())
+    }
+
+    fn read_page_setup(&mut self) -> Result<(), GpParseError> {
+        // page size, margins and score size proportion
+        self.skip(7 * 4)?;
+        let _header_and_footer = self.i16()?;
+        // title, subtitle, artist, album, words, music, words and music, two copyright lines and page number
+        for _ in 0..10 {
+            self.int_byte_size_string()?;
+        }
+        Ok(())
+    }
+
+    fn read_midi_channels(&mut self) -> Result<(), GpParseError> {
+        // 64 channels of instrument, volume, balance, chorus, reverb, phaser, tremolo and two blank bytes
+        self.skip(64 * 12)
+    }
+
+    fn read_song_header(&mut self) -> Result<(String, String, u32), GpParseError> {
+        self.read_version()?;
+        let (title, artist) = self.read_info()?;
+        let tempo = if self.is_gp5() {
+            self.read_lyrics()?;
+            if self.version > (5, 0) {
+                // master volume, unknown int and 11 equalizer knobs
+                self.skip(4 + 4 + 11)?;
+            }
+            self.read_page_setup()?;
+            let _tempo_name = self.int_byte_size_string()?;
+            let tempo = self.i32()?;
+            if self.version > (5, 0) {
+                let _hide_tempo = self.bool()?;
+            }
+            let _key = self.i8()?;
+            let _octave = self.i32()?;
+            self.read_midi_channels()?;
+            // musical directions, coda and segno positions
+            self.skip(19 * 2)?;
+            let _master_reverb = self.i32()?;
+            tempo
+        } else {
+            let _triplet_feel = self.bool()?;
+            if self.version.0 == 4 {
+                self.read_lyrics()?;
+            }
+            let tempo = self.i32()?;
+            let _key = self.i32()?;
+            if self.version.0 == 4 {
+                let _octave = self.i8()?;
+            }
+            self.read_midi_channels()?;
+            tempo
+        };
+        if tempo <= 0 {
+            return Err(self.error(GpParseErrorKind::InvalidValue("tempo")));
+        }
+        Ok((title, artist, tempo as u32))
+    }
+
+    fn read_marker(&mut self) -> Result<(), GpParseError> {
+        self.int_byte_size_string()?;
+        // rgb color and a blank byte
+        self.skip(4)
+    }
+
+    fn read_measure_header(&mut self, previous: Option<&MeasureHeader>) -> Result<MeasureHeader, GpParseError> {
+        if self.is_gp5() && previous.is_some() {
+            self.skip(1)?;
+        }
+        let flags = self.u8()?;
+        let mut header = MeasureHeader {
+            numerator: previous.map(|p| p.numerator).unwrap_or(4),
+            denominator: previous.map(|p| p.denominator).unwrap_or(4),
+            repeat: Repeat {
+                open: flags & 0x04 != 0,
+                ..Repeat::default()
+            },
+        };
+        if flags & 0x01 != 0 {
+            header.numerator = self.u8()?;
+        }
+        if flags & 0x02 != 0 {
+            header.denominator = self.u8()?;
+        }
+        if header.numerator == 0 || header.denominator == 0 {
+            return Err(self.error(GpParseErrorKind::InvalidValue("time signature")));
+        }
+        if flags & 0x08 != 0 {
+            let repeat = self.u8()?;
+            // guitar pro 5 counts plays, older versions count repeats
+            header.repeat.close = if self.is_gp5() { repeat.saturating_sub(1) } else { repeat };
+        }
+        if !self.is_gp5() && flags & 0x10 != 0 {
+            let ending = self.u8()?;
+            header.repeat.alternative = if ending > 0 && ending <= 8 { 1 << (ending - 1) } else { 0 };
+        }
+        if flags & 0x20 != 0 {
+            self.read_marker()?;
+        }
+        if flags & 0x40 != 0 {
+            let _root = self.i8()?;
+            let _kind = self.i8()?;
+        }
+        if self.is_gp5() {
+            // guitar pro 5 keeps the ending ahead of the beaming, and a placeholder without one
+            if flags & 0x10 != 0 {
+                header.repeat.alternative = self.u8()?;
+            }
+            if flags & 0x03 != 0 {
+                // beaming
+                self.skip(4)?;
+            }
+            if flags & 0x10 == 0 {
+                self.skip(1)?;
+            }
+            let _triplet_feel = self.u8()?;
+        }
+        Ok(header)
+    }
+
+    fn read_track(&mut self, index: usize) -> Result<TrackHeader, GpParseError> {
+        if self.is_gp5() && (index == 0 || self.version == (5, 0)) {
+            self.skip(1)?;
+        }
+        let flags = self.u8()?;
+        let name = self.byte_size_string(40)?;
+        let string_count = self.i32()?;
+        if !(1..=7).contains(&string_count) {
+            return Err(self.error(GpParseErrorKind::InvalidValue("string count")));
+        }
+        let mut tuning = vec![];
+        for string in 0..7 {
+            let pitch = self.i32()?;
+            if string < string_count {
+                tuning.push(pitch.clamp(0, 127) as u8);
+            }
+        }
+        let _port = self.i32()?;
+        let _channel = self.i32()?;
+        let _effect_channel = self.i32()?;
+        let frets = self.i32()?;
+        let capo = self.i32()?;
+        // color
+        self.skip(4)?;
+        if self.is_gp5() {
+            let _flags = self.i16()?;
+            let _auto_accentuation = self.u8()?;
+            let _bank = self.u8()?;
+            let _humanize = self.u8()?;
+            // unknown ints and bytes
+            self.skip(3 * 4 + 12)?;
+            self.read_rse_instrument()?;
+            if self.version > (5, 0) {
+                // equalizer knobs, effect and effect category
+                self.skip(4)?;
+                self.int_byte_size_string()?;
+                self.int_byte_size_string()?;
+            }
+        }
+        Ok(TrackHeader {
+            name,
+            percussion: flags & 0x01 != 0,
+            tuning,
+            frets: frets.clamp(0, 255) as u8,
+            capo: capo.clamp(0, 255) as u8,
+        })
+    }
+
+    fn read_rse_instrument(&mut self) -> Result<(), GpParseError> {
+        let _instrument = self.i32()?;
+        let _unknown = self.i32()?;
+        let _sound_bank = self.i32()?;
+        if self.version == (5, 0) {
+            let _effect_number = self.i16()?;
+            self.skip(1)
+        } else {
+            self.i32().map(|_| ())
+        }
+    }
+
+    fn read_chord(&mut self) -> Result<(), GpParseError> {
+        let new_format = self.bool()?;
+        if !new_format {
+            self.int_byte_size_string()?;
+            let first_fret = self.i32()?;
+            if first_fret != 0 {
+                self.skip(6 * 4)?;
+            }
+        } else if self.version.0 == 3 {
+            // sharp and blank bytes, root, type, extension, bass and tonality ints, add
+            self.skip(4 + 5 * 4 + 1)?;
+            self.byte_size_string(22)?;
+            // fifth, ninth, eleventh, first fret, six frets, barre count, frets, starts and ends, omissions, blank byte
+            self.skip(3 * 4 + 4 + 6 * 4 + 4 + 3 * 2 * 4 + 7 + 1)?;
+        } else {
+            // sharp and blank bytes, root, type and extension bytes, bass and tonality ints, add
+            self.skip(4 + 3 + 2 * 4 + 1)?;
+            self.byte_size_string(22)?;
+            // fifth, ninth and eleventh bytes, first fret, seven frets, barre count, frets, starts and ends,
+            // omissions, blank byte, fingerings and show flag
+            self.skip(3 + 4 + 7 * 4 + 1 + 3 * 5 + 7 + 1 + 7 + 1)?;
+        }
+        Ok(())
+    }
+
+    /// returns the highest bend point
+    fn read_bend(&mut self) -> Result<i32, GpParseError> {
+        let _kind = self.i8()?;
+        let value = self.i32()?;
+        let points = self.i32()?;
+        if points < 0 {
+            return Err(self.error(GpParseErrorKind::InvalidValue("bend point count")));
+        }
+        let mut highest = if points == 0 { value } else { 0 };
+        for _ in 0..points {
+            let _position = self.i32()?;
+            highest = highest.max(self.i32()?);
+            let _vibrato = self.bool()?;
+        }
+        Ok(highest)
+    }
+
+    fn read_beat_effects(&mut self) -> Result<Techniques, GpParseError> {
+        let mut techniques = Techniques::default();
+        if self.version.0 == 3 {
+            let flags = self.u8()?;
+            techniques.vibrato = flags & 0x03 != 0;
+            if flags & 0x20 != 0 {
+                // tapping, slapping or popping followed by an unused int, or a tremolo bar value
+                techniques.tap = self.u8()? == 1;
+                self.skip(4)?;
+            }
+            if flags & 0x40 != 0 {
+                // stroke up and down
+                self.skip(2)?;
+            }
+        } else {
+            let flags1 = self.u8()?;
+            let flags2 = self.u8()?;
+            techniques.vibrato = flags1 & 0x02 != 0;
+            if flags1 & 0x20 != 0 {
+                // tapping, slapping or popping
+                techniques.tap = self.i8()? == 1;
+            }
+            if flags2 & 0x04 != 0 {
+                let _tremolo_bar = self.read_bend()?;
+            }
+            if flags1 & 0x40 != 0 {
+                // stroke up and down
+                self.skip(2)?;
+            }
+            if flags2 & 0x02 != 0 {
+                let _pick_stroke = self.i8()?;
+            }
+        }
+        Ok(techniques)
+    }
+
+    fn read_mix_table_change(&mut self) -> Result<Option<u32>, GpParseError> {
+        let _instrument = self.i8()?;
+        if self.is_gp5() {
+            self.read_rse_instrument()?;
+            if self.version == (5, 0) {
+                self.skip(1)?;
+            }
+        }
+        let mut values = [0i8; 6];
+        for value in values.iter_mut() {
+            *value = self.i8()?;
+        }
+        if self.is_gp5() {
+            let _tempo_name = self.int_byte_size_string()?;
+        }
+        let tempo = self.i32()?;
+        for value in values.iter() {
+            if *value >= 0 {
+                let _transition = self.i8()?;
+            }
+        }
+        if tempo >= 0 {
+            let _transition = self.i8()?;
+            if self.version > (5, 0) {
+                let _hide_tempo = self.bool()?;
+            }
+        }
+        if self.version.0 >= 4 {
+            let _apply_to_all_tracks = self.i8()?;
+        }
+        if self.is_gp5() {
+            let _wah = self.i8()?;
+            if self.version > (5, 0) {
+                self.int_byte_size_string()?;
+                self.int_byte_size_string()?;
+            }
+        }
+        Ok(if tempo > 0 { Some(tempo as u32) } else { None })
+    }
+
+    fn read_note_effects(&mut self, note: &mut Note) -> Result<(), GpParseError> {
+        if self.version.0 == 3 {
+            let flags = self.u8()?;
+            if flags & 0x01 != 0 {
+                note.bend = self.read_bend()?;
+            }
+            if flags & 0x10 != 0 {
+                // grace note fret, velocity, duration and transition
+                self.skip(4)?;
+            }
+            note.slide = flags & 0x04 != 0;
+            note.legato = flags & 0x02 != 0;
+            return Ok(());
+        }
+        let flags1 = self.u8()?;
+        let flags2 = self.u8()?;
+        note.legato = flags1 & 0x02 != 0;
+        note.techniques.palm_mute = flags2 & 0x02 != 0;
+        note.techniques.vibrato = flags2 & 0x40 != 0;
+        if flags1 & 0x01 != 0 {
+            note.bend = self.read_bend()?;
+        }
+        if flags1 & 0x10 != 0 {
+            // grace note, guitar pro 5 adds a flags byte
+            self.skip(if self.is_gp5() { 5 } else { 4 })?;
+        }
+        if flags2 & 0x04 != 0 {
+            // tremolo picking speed
+            let _speed = self.i8()?;
+            note.techniques.tremolo = true;
+        }
+        if flags2 & 0x08 != 0 {
+            let slide = self.i8()?;
+            note.slide = if self.is_gp5() {
+                // shift or legato slide to the next note
+                slide & 0x03 != 0
+            } else {
+                slide == 1 || slide == 2
+            };
+        }
+        if flags2 & 0x10 != 0 {
+            let harmonic = self.i8()?;
+            note.techniques.harmonic = match harmonic {
+                1 => Some(Harmonic::Natural),
+                4 => Some(Harmonic::Pinch),
+                _ => None,
+            };
+            if self.is_gp5() {
+                match harmonic {
+                    // artificial harmonic note, accidental and octave
+                    2 => self.skip(3)?,
+                    // tapped harmonic fret
+                    3 => self.skip(1)?,
+                    _ => (),
+                }
+            }
+        }
+        if flags2 & 0x20 != 0 {
+            // trill fret and period
+            self.skip(2)?;
+        }
+        Ok(())
+    }
+
+    fn read_note(&mut self, string: u8) -> Result<Note, GpParseError> {
+        let flags = self.u8()?;
+        let mut note = Note {
+            string,
+            fret: 0,
+            kind: NoteKind::Normal,
+            bend: 0,
+            slide: false,
+            legato: false,
+            techniques: Techniques::default(),
+        };
+        if flags & 0x20 != 0 {
+            note.kind = match self.u8()? {
+                2 => NoteKind::Tie,
+                3 => NoteKind::Dead,
+                _ => NoteKind::Normal,
+            };
+        }
+        if !self.is_gp5() && flags & 0x01 != 0 {
+            // time independent duration and tuplet
+            self.skip(2)?;
+        }
+        if flags & 0x10 != 0 {
+            let _velocity = self.i8()?;
+        }
+        if flags & 0x20 != 0 {
+            note.fret = self.i8()?.max(0) as u8;
+        }
+        if flags & 0x80 != 0 {
+            // left and right hand fingering
+            self.skip(2)?;
+        }
+        if self.is_gp5() {
+            if flags & 0x01 != 0 {
+                // duration percent as double
+                self.skip(8)?;
+            }
+            let _flags2 = self.u8()?;
+        }
+        if flags & 0x08 != 0 {
+            self.read_note_effects(&mut note)?;
+        }
+        Ok(note)
+    }
+
+    fn read_duration(&mut self, flags: u8) -> Result<f64, GpParseError> {
+        let value = self.i8()?;
+        if !(-2..=6).contains(&value) {
+            return Err(self.error(GpParseErrorKind::InvalidValue("duration")));
+        }
+        let mut quarters = 4.0 / f64::from(1u32 << (value + 2));
+        if flags & 0x01 != 0 {
+            quarters *= 1.5;
+        }
+        if flags & 0x20 != 0 {
+            let enters = self.i32()?;
+            let times = match enters {
+                3 => 2,
+                5..=7 => 4,
+                9..=13 => 8,
+                _ => enters,
+            };
+            if enters > 0 {
+                quarters *= f64::from(times) / f64::from(enters);
+            }
+        }
+        Ok(quarters)
+    }
+
+    fn read_beat(&mut self, start: f64, string_count: usize) -> Result<Beat, GpParseError> {
+        let flags = self.u8()?;
+        let empty = if flags & 0x40 != 0 { self.u8()? == 0 } else { false };
+        let duration = self.read_duration(flags)?;
+        if flags & 0x02 != 0 {
+            self.read_chord()?;
+        }
+        if flags & 0x04 != 0 {
+            let _text = self.int_byte_size_string()?;
+        }
+        let techniques = if flags & 0x08 != 0 {
+            self.read_beat_effects()?
+        } else {
+            Techniques::default()
+        };
+        let tempo = if flags & 0x10 != 0 { self.read_mix_table_change()? } else { None };
+        let string_flags = self.u8()?;
+        let mut notes = vec![];
+        for string in 1..=string_count as u8 {
+            if string_flags & (1 << (7 - string)) != 0 {
+                notes.push(self.read_note(string)?);
+            }
+        }
+        if self.is_gp5() {
+            let display_flags = self.i16()?;
+            if display_flags & 0x0800 != 0 {
+                let _break_secondary = self.u8()?;
+            }
+        }
+        Ok(Beat {
+            start,
+            duration: if empty { 0.0 } else { duration },
+            notes,
+            tempo,
+            techniques,
+        })
+    }
+
+    fn read_measure(&mut self, string_count: usize) -> Result<Vec<Beat>, GpParseError> {
+        let mut beats = vec![];
+        let voices = if self.is_gp5() { 2 } else { 1 };
+        for _ in 0..voices {
+            let count = self.i32()?;
+            let mut start = 0.0;
+            for _ in 0..count {
+                let beat = self.read_beat(start, string_count)?;
+                start += beat.duration;
+                beats.push(beat);
+            }
+        }
+        if self.is_gp5() {
+            let _line_break = self.u8()?;
+        }
+        Ok(beats)
+    }
+}
+
+fn track_data(order: &[(f64, usize)], measures: &[Vec<Vec<Beat>>], track: usize, tempo: &TempoMap) -> TrackData {
+    let mut chart = ChartBuilder::default();
+    // fret of the last note on every string that hammers or pulls to the next one
+    let mut legato: HashMap<u8, u8> = HashMap::new();
+    for (measure_start, measure) in order {
+        for beat in &measures[*measure][track] {
+            let starts_at = tempo.time_of_quarter(measure_start + beat.start);
+            let ends_at = tempo.time_of_quarter(measure_start + beat.start + beat.duration);
+            for note in &beat.notes {
+                match note.kind {
+                    NoteKind::Tie => chart.tie(note.string, ends_at),
+                    NoteKind::Normal | NoteKind::Dead => {
+                        let mut techniques = note.techniques.clone();
+                        techniques.tap |= beat.techniques.tap;
+                        techniques.vibrato |= beat.techniques.vibrato;
+                        techniques.dead = note.kind == NoteKind::Dead;
+                        if let Some(from) = legato.remove(&note.string) {
+                            techniques.hammer_on = note.fret > from;
+                            techniques.pull_off = note.fret < from;
+                        }
+                        if note.legato {
+                            legato.insert(note.string, note.fret);
+                        }
+                        let fret = Fret {
+                            fret: note.fret,
+                            string: note.string,
+                            starts_at,
+                            ends_at,
+                            techniques,
+                        };
+                        let bend = if note.bend > 0 {
+                            Some(bend_interval(f64::from(note.bend) / BEND_SEMITONE))
+                        } else {
+                            None
+                        };
+                        chart.note(fret, bend, note.slide);
+                    }
+                }
+            }
+        }
+    }
+    chart.build(tempo.clone())
+}
+
+/// parse a guitar pro 3, 4 or 5 file into one chart per non percussion track,
+/// repeats are expanded and tempo changes applied so that all times are absolute,
+/// slapping, popping, grace notes and trills are played as plain notes
+pub fn parse_song(bytes: &[u8]) -> Result<GpSong, GpParseError> {
+    let mut reader = Reader {
+        bytes,
+        offset: 0,
+        version: (0, 0),
+    };
+    let (title, artist, tempo) = reader.read_song_header()?;
+    let measure_count = reader.i32()?;
+    let track_count = reader.i32()?;
+    if measure_count < 0 || track_count < 0 {
+        return Err(reader.error(GpParseErrorKind::InvalidValue("measure or track count")));
+    }
+    let mut headers: Vec<MeasureHeader> = vec![];
+    for _ in 0..measure_count {
+        let header = reader.read_measure_header(headers.last())?;
+        headers.push(header);
+    }
+    let mut tracks = vec![];
+    for index in 0..track_count as usize {
+        tracks.push(reader.read_track(index)?);
+    }
+    if reader.is_gp5() {
+        reader.skip(if reader.version == (5, 0) { 2 } else { 1 })?;
+    }
+    let mut measures: Vec<Vec<Vec<Beat>>> = vec![];
+    for _ in 0..headers.len() {
+        let mut measure = vec![];
+        for track in &tracks {
+            measure.push(reader.read_measure(track.tuning.len())?);
+        }
+        measures.push(measure);
+    }
+
+    let mut order = vec![];
+    let mut tempos = vec![(0.0, f64::from(tempo))];
+    let mut position = 0.0;
+    let repeats: Vec<Repeat> = headers.iter().map(|h| h.repeat.clone()).collect();
+    for index in playback_order(&repeats) {
+        for beats in &measures[index] {
+            tempos.extend(beats.iter().filter_map(|b| b.tempo.map(|t| (position + b.start, f64::from(t)))));
+        }
+        order.push((position, index));
+        position += headers[index].quarters();
+    }
+    let signatures = time_signatures(order.iter().map(|(_, index)| (headers[*index].numerator, headers[*index].denominator)));
+    let tempo_map = TempoMap::from_quarters(tempos, signatures);
+
+    let tracks = tracks
+        .into_iter()
+        .enumerate()
+        .filter(|(_, track)| !track.percussion)
+        .map(|(index, track)| GpTrack {
+            name: track.name,
+            tuning: Tuning::new(track.tuning).with_capo(track.capo),
+            frets: track.frets,
+            data: track_data(&order, &measures, index, &tempo_map),
+        })
+        .collect();
+    Ok(GpSong {
+        title,
+        artist,
+        tempo,
+        tracks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::format::chart::playback_order;
+    use crate::track::fret;
+    use crate::track::fret_action;
+    use crate::track::hand_position;
+    use crate::track::Action;
+    use crate::track::Interval;
+    use std::time::Duration;
+
+    fn check_riff(bytes: &[u8]) {
+        let song = parse_song(bytes).unwrap();
+        assert_eq!("Fixture Song", song.title);
+        assert_eq!("Fixture Band", song.artist);
+        assert_eq!(120, song.tempo);
+        assert_eq!(1, song.tracks.len());
+        let track = &song.tracks[0];
+        assert_eq!("Guitar", track.name);
+        assert_eq!(Tuning::standard(), track.tuning);
+        assert_eq!(24, track.frets);
+        let hammered = Fret {
+            techniques: Techniques {
+                hammer_on: true,
+                ..Techniques::default()
+            },
+            ..fret(500, 1000, 7, 3)
+        };
+        assert_eq!(
+            vec![
+                fret_action(0, 500, 5, 3),
+                Action::Fret(hammered),
+                Action::Slide(fret(1000, 1250, 5, 2), fret(1250, 1500, 7, 2)),
+                Action::Bend(fret(1500, 2000, 7, 3), Interval::Step),
+                fret_action(2000, 3000, 0, 1),
+                fret_action(2000, 3000, 1, 2),
+                fret_action(3000, 5000, 3, 4),
+            ],
+            track.data.actions
+        );
+        assert_eq!(vec![hand_position(0, 5), hand_position(2000, 1)], track.data.hand_positions);
+        // the tempo drops to 60 at the second measure, 4 quarters of 500ms in
+        let tempos: Vec<(Duration, f64)> = track.data.tempo.tempos.iter().map(|t| (t.at, t.bpm)).collect();
+        assert_eq!(vec![(Duration::from_millis(0), 120.0), (Duration::from_millis(2000), 60.0)], tempos);
+        assert_eq!(Duration::from_millis(3000), track.data.tempo.time_of_beat(5.0));
+    }
+
+    #[test]
+    fn test_gp3() {
+        check_riff(include_bytes!("../../fixtures/riff.gp3"));
+    }
+
+    #[test]
+    fn test_gp4() {
+        check_riff(include_bytes!("../../fixtures/riff.gp4"));
+    }
+
+    #[test]
+    fn test_gp5() {
+        check_riff(include_bytes!("../../fixtures/riff.gp5"));
+    }
+
+    #[test]
+    fn test_gp5_measure_headers() {
+        #[rustfmt::skip]
+        let bytes = [
+            // open repeat in 3/4
+            0x07, 3, 4, 2, 2, 2, 2, 0, 0,
+            // first ending in 2/4 closing the repeat after two plays
+            0, 0x19, 2, 2, 0x01, 2, 2, 2, 2, 0,
+            // second ending
+            0, 0x10, 0x02, 0,
+        ];
+        let mut reader = Reader {
+            bytes: &bytes,
+            offset: 0,
+            version: (5, 10),
+        };
+        let mut headers: Vec<MeasureHeader> = vec![];
+        for _ in 0..3 {
+            let header = reader.read_measure_header(headers.last()).unwrap();
+            headers.push(header);
+        }
+        assert_eq!(bytes.len(), reader.offset);
+        let signatures: Vec<(u8, u8)> = headers.iter().map(|h| (h.numerator, h.denominator)).collect();
+        assert_eq!(vec![(3, 4), (2, 4), (2, 4)], signatures);
+        let repeats: Vec<Repeat> = headers.iter().map(|h| h.repeat.clone()).collect();
+        assert_eq!(vec![0, 1, 0, 2], playback_order(&repeats));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let bytes = include_bytes!("../../fixtures/riff.gp5");
+        let error = parse_song(&bytes[..bytes.len() - 4]).unwrap_err();
+        assert_eq!(GpParseErrorKind::UnexpectedEnd, error.kind);
+        let error = parse_song(b"\x05hello").unwrap_err();
+        assert_eq!(GpParseErrorKind::UnexpectedEnd, error.kind);
+    }
+}
@@ -0,0 +1,199 @@
+use crate::track::Lyric;
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum LrcParseErrorKind {
+    /// a tag starting like a time that isn't `mm:ss.xx`
+    InvalidTime(String),
+    InvalidOffset(String),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct LrcParseError {
+    pub kind: LrcParseErrorKind,
+    pub line: usize,
+}
+
+impl fmt::Display for LrcParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            LrcParseErrorKind::InvalidTime(tag) => write!(f, "line {}: invalid time `{}`, expected mm:ss.xx", self.line, tag),
+            LrcParseErrorKind::InvalidOffset(tag) => write!(f, "line {}: invalid offset `{}`, expected milliseconds", self.line, tag),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct LrcLyrics {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    /// whole lines, or syllables where the file times single words
+    pub lyrics: Vec<Lyric>,
+}
+
+/// milliseconds of a `mm:ss` or `mm:ss.xx` time, any number of fraction digits
+fn parse_time(tag: &str) -> Option<i64> {
+    let (minutes, seconds) = tag.split_once(':')?;
+    let (seconds, fraction) = match seconds.split_once('.') {
+        Some((seconds, fraction)) => (seconds, fraction),
+        None => (seconds, "0"),
+    };
+    let digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    if !digits(minutes) || !digits(seconds) || !digits(fraction) {
+        return None;
+    }
+    let millis: String = fraction.chars().chain("00".chars()).take(3).collect();
+    Some((minutes.parse::<i64>().ok()? * 60 + seconds.parse::<i64>().ok()?) * 1000 + millis.parse::<i64>().ok()?)
+}
+
+/// the text after the line times split at the word times inside it, `<mm:ss.xx>`
+fn split_words(text: &str, at: i64, line: usize) -> Result<Vec<(i64, &str)>, LrcParseError> {
+    let mut words = vec![];
+    let mut rest = text;
+    let mut current = at;
+    while let Some(start) = rest.find('<') {
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => break,
+        };
+        words.push((current, &rest[..start]));
+        let tag = &rest[start + 1..end];
+        current = parse_time(tag).ok_or_else(|| LrcParseError {
+            kind: LrcParseErrorKind::InvalidTime(tag.to_string()),
+            line,
+        })?;
+        rest = &rest[end + 1..];
+    }
+    words.push((current, rest));
+    Ok(words)
+}
+
+/// parse an lrc file, lines carry one or more `[mm:ss.xx]` times and may time single
+/// words with `<mm:ss.xx>`, an `[offset:<millis>]` tag moves every lyric earlier by that much
+pub fn parse_lrc(content: &str) -> Result<LrcLyrics, LrcParseError> {
+    let mut title = None;
+    let mut artist = None;
+    let mut offset = 0;
+    let mut lines: Vec<(i64, &str, usize)> = vec![];
+    for (index, line) in content.lines().enumerate() {
+        let mut rest = line.trim();
+        let mut times = vec![];
+        while rest.starts_with('[') {
+            let end = match rest.find(']') {
+                Some(end) => end,
+                None => break,
+            };
+            let tag = &rest[1..end];
+            rest = &rest[end + 1..];
+            if tag.starts_with(|c: char| c.is_ascii_digit()) {
+                let time = parse_time(tag).ok_or_else(|| LrcParseError {
+                    kind: LrcParseErrorKind::InvalidTime(tag.to_string()),
+                    line: index + 1,
+                })?;
+                times.push(time);
+                continue;
+            }
+            match tag.split_once(':') {
+                Some(("ti", value)) => title = Some(value.trim().to_string()),
+                Some(("ar", value)) => artist = Some(value.trim().to_string()),
+                Some(("offset", value)) => {
+                    offset = value.trim().trim_start_matches('+').parse().map_err(|_| LrcParseError {
+                        kind: LrcParseErrorKind::InvalidOffset(value.to_string()),
+                        line: index + 1,
+                    })?
+                }
+                _ => (),
+            }
+        }
+        lines.extend(times.into_iter().map(|time| (time, rest, index + 1)));
+    }
+    // lines repeated under several times are read once for each
+    lines.sort_by_key(|(time, _, _)| *time);
+
+    let mut lyrics = vec![];
+    for (time, text, line) in lines {
+        let words = split_words(text, time, line)?;
+        let mut line_lyrics: Vec<Lyric> = vec![];
+        for (index, (at, word)) in words.iter().enumerate() {
+            let trimmed = word.trim();
+            if trimmed.is_empty() {
+                continue;
+            }
+            // a word timed in parts runs on into the next part
+            let joined = index + 1 < words.len() && !word.ends_with(char::is_whitespace);
+            line_lyrics.push(Lyric {
+                at: Duration::from_millis((at - offset).max(0) as u64),
+                text: if joined { format!("{}-", trimmed) } else { trimmed.to_string() },
+                line_end: false,
+            });
+        }
+        // lines without text only mark a break in the singing
+        if let Some(last) = line_lyrics.last_mut() {
+            last.text = last.text.trim_end_matches('-').to_string();
+            last.line_end = true;
+        }
+        lyrics.extend(line_lyrics);
+    }
+    Ok(LrcLyrics { title, artist, lyrics })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::track_data;
+    use crate::track::LyricLine;
+    use crate::track::TrackData;
+
+    #[test]
+    fn test_parse_lrc() {
+        let content = "[ti:Song]\n\
+                       [ar:Band]\n\
+                       [offset:+100]\n\
+                       [00:12.00]Is this the real life\n\
+                       [00:15.50][01:00.5]Chorus line\n\
+                       [00:20.00]\n\
+                       [00:21.00]<00:21.00>Hel<00:21.25>lo <00:21.75>world\n";
+        let lrc = parse_lrc(content).unwrap();
+        assert_eq!(
+            (Some("Song".to_string()), Some("Band".to_string())),
+            (lrc.title.clone(), lrc.artist.clone())
+        );
+        let lyrics: Vec<(u64, &str, bool)> = lrc
+            .lyrics
+            .iter()
+            .map(|l| (l.at.as_millis() as u64, l.text.as_str(), l.line_end))
+            .collect();
+        assert_eq!(
+            vec![
+                (11900, "Is this the real life", true),
+                (15400, "Chorus line", true),
+                (20900, "Hel-", false),
+                (21150, "lo", false),
+                (21650, "world", true),
+                (60400, "Chorus line", true),
+            ],
+            lyrics
+        );
+        let data = TrackData {
+            lyrics: lrc.lyrics,
+            ..track_data(vec![], vec![])
+        };
+        assert_eq!(
+            LyricLine {
+                starts_at: Duration::from_millis(20900),
+                text: "Hello world".to_string(),
+            },
+            data.lyric_lines()[2]
+        );
+    }
+
+    #[test]
+    fn test_parse_lrc_errors() {
+        let error = parse_lrc("[00:01.00]fine\n[1:xx]broken").unwrap_err();
+        assert_eq!((LrcParseErrorKind::InvalidTime("1:xx".to_string()), 2), (error.kind.clone(), error.line));
+        assert_eq!("line 2: invalid time `1:xx`, expected mm:ss.xx", error.to_string());
+        let error = parse_lrc("[offset:soon]").unwrap_err();
+        assert_eq!(LrcParseErrorKind::InvalidOffset("soon".to_string()), error.kind);
+    }
+}
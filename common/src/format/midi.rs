@@ -0,0 +1,567 @@
+use crate::fingering::finger_notes;
+use crate::tempo::TempoMap;
+use crate::tempo::TimeSignature;
+use crate::track::Action;
+use crate::track::Fret;
+use crate::track::TrackData;
+use crate::tuning::Tuning;
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+/// channel 10 is reserved for drums in general midi
+const PERCUSSION_CHANNEL: u8 = 9;
+/// channels left for strings besides percussion
+const STRING_CHANNELS: u8 = 15;
+/// ticks per quarter note in exported files
+const EXPORT_DIVISION: u16 = 480;
+/// pitch bend range set on every exported channel, wide enough for long slides
+const BEND_RANGE: f64 = 24.0;
+/// pitch bend messages written for a bend or glissando
+const BEND_STEPS: u64 = 8;
+/// acoustic guitar (steel)
+const GUITAR_PROGRAM: u8 = 25;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum MidiParseErrorKind {
+    InvalidHeader,
+    UnsupportedDivision,
+    UnexpectedEnd,
+    InvalidValue(&'static str),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MidiParseError {
+    pub kind: MidiParseErrorKind,
+    pub offset: usize,
+}
+
+impl fmt::Display for MidiParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            MidiParseErrorKind::InvalidHeader => write!(f, "not a standard midi file"),
+            MidiParseErrorKind::UnsupportedDivision => write!(f, "smpte time division is not supported"),
+            MidiParseErrorKind::UnexpectedEnd => write!(f, "unexpected end of file at byte {}", self.offset),
+            MidiParseErrorKind::InvalidValue(what) => write!(f, "invalid {} at byte {}", what, self.offset),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MidiTrack {
+    pub name: String,
+    pub channel: u8,
+    pub data: TrackData,
+    /// notes that could not be placed on any string of the tuning
+    pub unplayable: usize,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MidiSong {
+    pub title: String,
+    pub tracks: Vec<MidiTrack>,
+}
+
+struct Note {
+    start: u64,
+    end: u64,
+    pitch: u8,
+}
+
+#[derive(Default)]
+struct Chunk {
+    name: String,
+    /// tick and microseconds per quarter note
+    tempos: Vec<(u64, u32)>,
+    /// tick, numerator and denominator
+    signatures: Vec<(u64, u8, u8)>,
+    notes: HashMap<u8, Vec<Note>>,
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error(&self, kind: MidiParseErrorKind) -> MidiParseError {
+        MidiParseError { kind, offset: self.offset }
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], MidiParseError> {
+        let end = match self.offset.checked_add(count) {
+            Some(end) if end <= self.bytes.len() => end,
+            _ => return Err(self.error(MidiParseErrorKind::UnexpectedEnd)),
+        };
+        let bytes = &self.bytes[self.offset..end];
+        self.offset = end;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, MidiParseError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, MidiParseError> {
+        let bytes = self.take(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Result<u32, MidiParseError> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// variable length quantity, 7 bits per byte with the high bit set on all but the last
+    fn var(&mut self) -> Result<u32, MidiParseError> {
+        let mut value: u32 = 0;
+        for _ in 0..4 {
+            let byte = self.u8()?;
+            value = (value << 7) | u32::from(byte & 0x7f);
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err(self.error(MidiParseErrorKind::InvalidValue("variable length quantity")))
+    }
+
+    fn read_chunk(&mut self) -> Result<Option<Chunk>, MidiParseError> {
+        let kind = self.take(4)?;
+        let length = self.u32()? as usize;
+        // lengths come straight from the file, a 32 bit usize overflows on the largest ones
+        let end = self
+            .offset
+            .checked_add(length)
+            .ok_or_else(|| self.error(MidiParseErrorKind::InvalidValue("chunk length")))?;
+        if kind != b"MTrk" {
+            self.take(length)?;
+            return Ok(None);
+        }
+        let mut chunk = Chunk::default();
+        let mut tick: u64 = 0;
+        let mut status: u8 = 0;
+        let mut sounding: HashMap<(u8, u8), Vec<u64>> = HashMap::new();
+        while self.offset < end {
+            tick += u64::from(self.var()?);
+            let mut byte = self.u8()?;
+            if byte & 0x80 != 0 {
+                status = byte;
+                if byte < 0xf0 {
+                    byte = self.u8()?;
+                }
+            } else if status == 0 || status >= 0xf0 {
+                return Err(self.error(MidiParseErrorKind::InvalidValue("running status")));
+            }
+            let channel = status & 0x0f;
+            match status {
+                0xff => {
+                    let kind = self.u8()?;
+                    let length = self.var()? as usize;
+                    let data = self.take(length)?;
+                    match kind {
+                        0x03 => chunk.name = String::from_utf8_lossy(data).trim().to_string(),
+                        0x51 if length == 3 => {
+                            let tempo = u32::from_be_bytes([0, data[0], data[1], data[2]]);
+                            chunk.tempos.push((tick, tempo));
+                        }
+                        // the denominator is stored as a power of two
+                        0x58 if length >= 2 && data[1] < 8 => chunk.signatures.push((tick, data[0], 1 << data[1])),
+                        0x2f => break,
+                        _ => (),
+                    }
+                    // meta events do not take part in running status
+                    status = 0;
+                }
+                0xf0 | 0xf7 => {
+                    let length = self.var()? as usize;
+                    self.take(length)?;
+                    status = 0;
+                }
+                0x80..=0x9f => {
+                    let pitch = byte;
+                    let velocity = self.u8()?;
+                    let starts = sounding.entry((channel, pitch)).or_default();
+                    if status & 0xf0 == 0x90 && velocity > 0 {
+                        starts.push(tick);
+                    } else if !starts.is_empty() {
+                        let start = starts.remove(0);
+                        chunk.notes.entry(channel).or_default().push(Note { start, end: tick, pitch });
+                    }
+                }
+                0xa0..=0xbf | 0xe0..=0xef => {
+                    self.u8()?;
+                }
+                0xc0..=0xdf => (),
+                _ => return Err(self.error(MidiParseErrorKind::InvalidValue("event"))),
+            }
+        }
+        if self.offset > end {
+            return Err(self.error(MidiParseErrorKind::InvalidValue("track length")));
+        }
+        self.offset = end;
+        // notes never released end with the track
+        for ((channel, pitch), starts) in sounding {
+            for start in starts {
+                chunk.notes.entry(channel).or_default().push(Note { start, end: tick, pitch });
+            }
+        }
+        Ok(Some(chunk))
+    }
+}
+
+/// parse a standard midi file into one chart per track and channel, placing notes on
+/// the strings of `tuning` with up to `frets` frets above the capo
+pub fn parse_midi(bytes: &[u8], tuning: &Tuning, frets: u8) -> Result<MidiSong, MidiParseError> {
+    let mut reader = Reader { bytes, offset: 0 };
+    let header_length = match reader.take(4) {
+        Ok(tag) if tag == b"MThd" => reader.u32()?,
+        _ => 0,
+    };
+    if header_length < 6 {
+        return Err(MidiParseError {
+            kind: MidiParseErrorKind::InvalidHeader,
+            offset: 0,
+        });
+    }
+    let _format = reader.u16()?;
+    let chunk_count = reader.u16()?;
+    let division = reader.u16()?;
+    // later versions of the format may add fields to the header
+    reader.take((header_length - 6) as usize)?;
+    if division & 0x8000 != 0 {
+        return Err(reader.error(MidiParseErrorKind::UnsupportedDivision));
+    }
+    if division == 0 {
+        return Err(reader.error(MidiParseErrorKind::InvalidValue("time division")));
+    }
+    let mut chunks = vec![];
+    while chunks.len() < usize::from(chunk_count) && reader.offset < bytes.len() {
+        if let Some(chunk) = reader.read_chunk()? {
+            chunks.push(chunk);
+        }
+    }
+
+    let quarters = |tick: u64| tick as f64 / f64::from(division);
+    let mut signature_changes: Vec<(u64, u8, u8)> = chunks.iter().flat_map(|c| c.signatures.iter().cloned()).collect();
+    signature_changes.sort_by_key(|(tick, _, _)| *tick);
+    let mut signatures: Vec<TimeSignature> = vec![];
+    let mut last_tick = 0;
+    for (tick, numerator, denominator) in signature_changes {
+        let (measure, length) = signatures
+            .last()
+            .map(|s| (s.measure, f64::from(s.numerator) * 4.0 / f64::from(s.denominator)))
+            .unwrap_or((0, 4.0));
+        let measure = measure + ((quarters(tick) - quarters(last_tick)) / length.max(0.25)).round() as u32;
+        signatures.retain(|s| s.measure != measure);
+        signatures.push(TimeSignature {
+            measure,
+            numerator: numerator.max(1),
+            denominator: denominator.max(1),
+        });
+        last_tick = tick;
+    }
+    let tempo = TempoMap::from_quarters(
+        chunks
+            .iter()
+            .flat_map(|c| c.tempos.iter())
+            // microseconds per quarter rarely divide a minute evenly, keep hundredths of a beat per minute
+            .map(|(tick, micros)| (quarters(*tick), (6_000_000_000.0 / f64::from((*micros).max(1))).round() / 100.0))
+            .collect(),
+        signatures,
+    );
+    let title = chunks
+        .iter()
+        .find(|c| c.notes.is_empty() && !c.name.is_empty())
+        .map(|c| c.name.clone())
+        .unwrap_or_default();
+
+    let mut tracks = vec![];
+    for chunk in chunks {
+        let mut channels: Vec<(u8, Vec<Note>)> = chunk.notes.into_iter().collect();
+        channels.sort_by_key(|(channel, _)| *channel);
+        for (channel, notes) in channels.into_iter().filter(|(channel, _)| *channel != PERCUSSION_CHANNEL) {
+            let notes: Vec<(Duration, Duration, u8)> = notes
+                .iter()
+                .map(|n| (tempo.time_of_quarter(quarters(n.start)), tempo.time_of_quarter(quarters(n.end)), n.pitch))
+                .collect();
+            let fingering = finger_notes(&notes, tuning, frets);
+            tracks.push(MidiTrack {
+                name: chunk.name.clone(),
+                channel,
+                data: TrackData {
+                    actions: fingering.actions,
+                    hand_positions: fingering.hand_positions,
+                    tempo: tempo.clone(),
+                    ..TrackData::default()
+                },
+                unplayable: fingering.unplayable,
+            });
+        }
+    }
+    Ok(MidiSong { title, tracks })
+}
+
+struct Writer {
+    /// tick, order among events of the same tick and message
+    events: Vec<(u64, u8, Vec<u8>)>,
+}
+
+fn write_var(out: &mut Vec<u8>, value: u64) {
+    let mut bytes = vec![(value & 0x7f) as u8];
+    let mut value = value >> 7;
+    while value > 0 {
+        bytes.push((value & 0x7f) as u8 | 0x80);
+        value >>= 7;
+    }
+    out.extend(bytes.iter().rev());
+}
+
+impl Writer {
+    fn note(&mut self, channel: u8, pitch: u8, from: u64, to: u64) {
+        self.events.push((from, 2, vec![0x90 | channel, pitch, 100]));
+        self.events.push((to, 0, vec![0x80 | channel, pitch, 0]));
+        // recenter after the note so the next one starts unbent
+        self.bend(channel, to, 0.0, 1);
+    }
+
+    fn bend(&mut self, channel: u8, at: u64, semitones: f64, order: u8) {
+        let value = (8192.0 + semitones / BEND_RANGE * 8192.0).round().clamp(0.0, 16383.0) as u16;
+        self.events
+            .push((at, order, vec![0xe0 | channel, (value & 0x7f) as u8, (value >> 7) as u8]));
+    }
+
+    fn glide(&mut self, channel: u8, from: u64, to: u64, semitones: f64) {
+        for step in 1..=BEND_STEPS {
+            let at = from + (to.saturating_sub(from)) * step / BEND_STEPS;
+            self.bend(channel, at, semitones * step as f64 / BEND_STEPS as f64, 3);
+        }
+    }
+}
+
+/// write `data` as a single track standard midi file, every string gets its own channel
+/// so that bends and slides on one string leave the others alone, strings past the
+/// fifteenth share channels with the first ones again
+pub fn write_midi(data: &TrackData, tuning: &Tuning) -> Vec<u8> {
+    let ticks = |at: &Duration| (data.tempo.quarter_at(*at).max(0.0) * f64::from(EXPORT_DIVISION)).round() as u64;
+    let channel = |string: u8| {
+        let channel = string.saturating_sub(1) % STRING_CHANNELS;
+        if channel >= PERCUSSION_CHANNEL {
+            channel + 1
+        } else {
+            channel
+        }
+    };
+    // dead notes have no pitch to play
+    let pitch = |fret: &Fret| Some(fret).filter(|f| !f.techniques.dead).and_then(|f| tuning.pitch_of(f));
+    let mut writer = Writer { events: vec![] };
+    for tempo in data.tempo.changes() {
+        let micros = (60_000_000.0 / tempo.bpm).round() as u32;
+        let mut message = vec![0xff, 0x51, 0x03];
+        message.extend(&micros.to_be_bytes()[1..]);
+        writer.events.push((ticks(&tempo.at), 0, message));
+    }
+    for signature in &data.tempo.time_signatures {
+        let at = data.tempo.time_of_measure(signature.measure);
+        let power = (f64::from(signature.denominator.max(1))).log2().round() as u8;
+        writer
+            .events
+            .push((ticks(&at), 0, vec![0xff, 0x58, 0x04, signature.numerator, power, 24, 8]));
+    }
+    for string in 1..=(tuning.strings.len() as u8).min(STRING_CHANNELS) {
+        let channel = channel(string);
+        // pitch bend sensitivity through registered parameter 0
+        for (controller, value) in &[(101, 0), (100, 0), (6, BEND_RANGE as u8), (38, 0)] {
+            writer.events.push((0, 1, vec![0xb0 | channel, *controller, *value]));
+        }
+        writer.events.push((0, 1, vec![0xc0 | channel, GUITAR_PROGRAM]));
+    }
+    for action in &data.actions {
+        match action {
+            Action::Fret(fret) => {
+                if let Some(pitch) = pitch(fret) {
+                    writer.note(channel(fret.string), pitch, ticks(&fret.starts_at), ticks(&fret.ends_at));
+                }
+            }
+            Action::Bend(fret, interval) => {
+                if let Some(pitch) = pitch(fret) {
+                    let (from, to) = (ticks(&fret.starts_at), ticks(&fret.ends_at));
+                    let channel = channel(fret.string);
+                    writer.note(channel, pitch, from, to);
+                    writer.glide(channel, from, from + (to.saturating_sub(from)) / 2, f64::from(interval.semitones()));
+                }
+            }
+            Action::Chord(chord) => {
+                for fret in &chord.frets {
+                    if let Some(pitch) = pitch(fret) {
+                        writer.note(channel(fret.string), pitch, ticks(&fret.starts_at), ticks(&fret.ends_at));
+                    }
+                }
+            }
+            Action::Slide(from, to) => {
+                if let (Some(start), Some(end)) = (pitch(from), pitch(to)) {
+                    let channel = channel(from.string);
+                    let semitones = f64::from(end) - f64::from(start);
+                    writer.note(channel, start, ticks(&from.starts_at), ticks(action.ends_at()));
+                    writer.glide(channel, ticks(&from.starts_at), ticks(&to.starts_at), semitones);
+                }
+            }
+        }
+    }
+    writer.events.sort_by_key(|(at, order, _)| (*at, *order));
+
+    let mut track = vec![];
+    let mut last = 0;
+    for (at, _, message) in writer.events {
+        write_var(&mut track, at - last);
+        track.extend(message);
+        last = at;
+    }
+    write_var(&mut track, 0);
+    track.extend(&[0xff, 0x2f, 0x00]);
+
+    let mut bytes = b"MThd".to_vec();
+    bytes.extend(&6u32.to_be_bytes());
+    bytes.extend(&0u16.to_be_bytes());
+    bytes.extend(&1u16.to_be_bytes());
+    bytes.extend(&EXPORT_DIVISION.to_be_bytes());
+    bytes.extend(b"MTrk");
+    bytes.extend(&(track.len() as u32).to_be_bytes());
+    bytes.extend(track);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ext::DurationExt;
+    use crate::tempo::TempoChange;
+    use crate::track::fret;
+    use crate::track::fret_action;
+    use crate::track::hand_position;
+    use crate::track::track_data;
+    use crate::track::Interval;
+
+    #[test]
+    fn test_parse_midi() {
+        let song = parse_midi(include_bytes!("../../fixtures/riff.mid"), &Tuning::standard(), 24).unwrap();
+        assert_eq!("Fixture Song", song.title);
+        assert_eq!(1, song.tracks.len());
+        let track = &song.tracks[0];
+        assert_eq!(("Guitar", 0, 1), (track.name.as_str(), track.channel, track.unplayable));
+        assert_eq!(
+            vec![
+                fret_action(0, 500, 3, 1),
+                fret_action(500, 1000, 3, 2),
+                fret_action(1000, 1500, 2, 3),
+                fret_action(1000, 1500, 2, 4),
+                fret_action(1500, 2500, 0, 5),
+            ],
+            track.data.actions
+        );
+        // the hand settles where it reaches the whole riff
+        assert_eq!(vec![hand_position(0, 2)], track.data.hand_positions);
+        let tempos: Vec<(u64, f64)> = track.data.tempo.tempos.iter().map(|t| (t.at.total_millis(), t.bpm)).collect();
+        assert_eq!(vec![(0, 120.0), (1500, 60.0)], tempos);
+    }
+
+    #[test]
+    fn test_write_midi() {
+        let data = TrackData {
+            tempo: TempoMap {
+                tempos: vec![
+                    TempoChange {
+                        at: Duration::from_millis(0),
+                        bpm: 120.0,
+                    },
+                    TempoChange {
+                        at: Duration::from_millis(1000),
+                        bpm: 90.0,
+                    },
+                ],
+                time_signatures: vec![TimeSignature {
+                    measure: 0,
+                    numerator: 3,
+                    denominator: 4,
+                }],
+            },
+            ..track_data(
+                vec![
+                    fret_action(0, 500, 3, 1),
+                    Action::Slide(fret(500, 750, 5, 2), fret(750, 1000, 7, 2)),
+                    Action::Bend(fret(1000, 1500, 7, 3), Interval::Step),
+                ],
+                vec![],
+            )
+        };
+        let bytes = write_midi(&data, &Tuning::standard());
+        let song = parse_midi(&bytes, &Tuning::standard(), 24).unwrap();
+        let mut notes: Vec<(u8, u64, u64, u8)> = song
+            .tracks
+            .iter()
+            .flat_map(|t| t.data.actions.iter().map(move |a| (t.channel, a)))
+            .map(|(channel, a)| {
+                let f = a.frets()[0];
+                (
+                    channel,
+                    f.starts_at.total_millis(),
+                    f.ends_at.total_millis(),
+                    Tuning::standard().pitch_of(f).unwrap(),
+                )
+            })
+            .collect();
+        notes.sort();
+        assert_eq!(vec![(0, 0, 500, 67), (1, 500, 1000, 64), (2, 1000, 1500, 62)], notes);
+        assert_eq!(data.tempo, song.tracks[0].data.tempo);
+        // a whole step on string 3 ends the bend at 8192 + 2 / 24 * 8192
+        assert!(bytes.windows(3).any(|w| w == [0xe2, 0x2b, 0x45]));
+        // the slide reaches two semitones above the starting note at the start of the target fret
+        assert!(bytes.windows(3).any(|w| w == [0xe1, 0x2b, 0x45]));
+    }
+
+    #[test]
+    fn test_write_many_strings() {
+        let mut strings = vec![64; 15];
+        strings.extend_from_slice(&[45, 40]);
+        let tuning = Tuning::new(strings);
+        let data = track_data(vec![fret_action(0, 500, 0, 16), fret_action(500, 1000, 0, 17)], vec![]);
+        let bytes = write_midi(&data, &tuning);
+        let song = parse_midi(&bytes, &Tuning::standard(), 24).unwrap();
+        let notes: Vec<(u8, u8)> = song
+            .tracks
+            .iter()
+            .flat_map(|t| {
+                t.data
+                    .actions
+                    .iter()
+                    .map(move |a| (t.channel, Tuning::standard().pitch_of(a.frets()[0]).unwrap()))
+            })
+            .collect();
+        assert_eq!(vec![(0, 45), (1, 40)], notes);
+    }
+
+    #[test]
+    fn test_parse_midi_errors() {
+        let error = parse_midi(b"RIFF", &Tuning::standard(), 24).unwrap_err();
+        assert_eq!(MidiParseErrorKind::InvalidHeader, error.kind);
+        let bytes = include_bytes!("../../fixtures/riff.mid");
+        let error = parse_midi(&bytes[..40], &Tuning::standard(), 24).unwrap_err();
+        assert_eq!(MidiParseErrorKind::UnexpectedEnd, error.kind);
+        // a track claiming the largest length runs out of bytes instead of overflowing
+        let mut crafted = bytes[..14].to_vec();
+        crafted.extend_from_slice(b"MTrk\xff\xff\xff\xff\x00\x90");
+        let error = parse_midi(&crafted, &Tuning::standard(), 24).unwrap_err();
+        assert_eq!(MidiParseErrorKind::UnexpectedEnd, error.kind);
+    }
+
+    #[test]
+    fn test_long_header() {
+        let bytes = include_bytes!("../../fixtures/riff.mid");
+        let mut long = bytes[..14].to_vec();
+        long[7] = 8;
+        long.extend_from_slice(&[0, 0]);
+        long.extend_from_slice(&bytes[14..]);
+        let song = parse_midi(&long, &Tuning::standard(), 24).unwrap();
+        let expected = parse_midi(bytes, &Tuning::standard(), 24).unwrap();
+        assert_eq!(expected.tracks[0].data, song.tracks[0].data);
+    }
+}
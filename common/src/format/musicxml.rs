@@ -0,0 +1,465 @@
+use crate::format::chart::bend_interval;
+use crate::format::chart::playback_order;
+use crate::format::chart::time_signatures;
+use crate::format::chart::ChartBuilder;
+use crate::format::chart::Repeat;
+use crate::tempo::TempoMap;
+use crate::track::Fret;
+use crate::track::Harmonic;
+use crate::track::Techniques;
+use crate::track::TrackData;
+use crate::tuning::Tuning;
+use roxmltree::Document;
+use roxmltree::Node;
+use std::fmt;
+use std::str::FromStr;
+
+/// musicxml assumes 120 quarter notes per minute until told otherwise
+const DEFAULT_TEMPO: f64 = 120.0;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum MusicXmlParseErrorKind {
+    Xml(String),
+    UnsupportedDocument(String),
+    InvalidValue(&'static str),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MusicXmlParseError {
+    pub kind: MusicXmlParseErrorKind,
+    pub line: u32,
+}
+
+impl fmt::Display for MusicXmlParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            MusicXmlParseErrorKind::Xml(error) => write!(f, "{}", error),
+            MusicXmlParseErrorKind::UnsupportedDocument(root) => write!(f, "unsupported document `{}`, expected score-partwise", root),
+            MusicXmlParseErrorKind::InvalidValue(what) => write!(f, "line {}: invalid {}", self.line, what),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MusicXmlPart {
+    pub name: String,
+    pub tuning: Tuning,
+    pub data: TrackData,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct MusicXmlScore {
+    pub title: String,
+    pub artist: String,
+    pub parts: Vec<MusicXmlPart>,
+}
+
+struct Note {
+    /// quarter notes from the start of the measure
+    start: f64,
+    duration: f64,
+    string: u8,
+    fret: u8,
+    tie: bool,
+    slide: bool,
+    bend: Option<f64>,
+    techniques: Techniques,
+}
+
+#[derive(Default)]
+struct Measure {
+    length: f64,
+    /// numerator and denominator
+    signature: (u8, u8),
+    repeat: Repeat,
+    tempos: Vec<(f64, f64)>,
+    notes: Vec<Note>,
+}
+
+struct Parser<'a, 'input> {
+    document: &'a Document<'input>,
+    divisions: f64,
+    signature: (u8, u8),
+    tuning: Tuning,
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|c| c.has_tag_name(name))
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name).and_then(|c| c.text()).map(|t| t.trim())
+}
+
+fn pitch_class(step: &str) -> Option<i32> {
+    match step {
+        "C" => Some(0),
+        "D" => Some(2),
+        "E" => Some(4),
+        "F" => Some(5),
+        "G" => Some(7),
+        "A" => Some(9),
+        "B" => Some(11),
+        _ => None,
+    }
+}
+
+impl<'a, 'input> Parser<'a, 'input> {
+    fn error(&self, node: Node, kind: MusicXmlParseErrorKind) -> MusicXmlParseError {
+        MusicXmlParseError {
+            kind,
+            line: self.document.text_pos_at(node.range().start).row,
+        }
+    }
+
+    fn value<T: FromStr>(&self, node: Node, name: &str, what: &'static str) -> Result<Option<T>, MusicXmlParseError> {
+        match child_text(node, name) {
+            Some(text) => text
+                .parse::<T>()
+                .map(Some)
+                .map_err(|_| self.error(node, MusicXmlParseErrorKind::InvalidValue(what))),
+            None => Ok(None),
+        }
+    }
+
+    fn duration(&self, node: Node) -> Result<f64, MusicXmlParseError> {
+        Ok(self.value::<f64>(node, "duration", "duration")?.unwrap_or(0.0) / self.divisions)
+    }
+
+    fn read_attributes(&mut self, node: Node) -> Result<(), MusicXmlParseError> {
+        if let Some(divisions) = self.value::<f64>(node, "divisions", "divisions")? {
+            if divisions <= 0.0 {
+                return Err(self.error(node, MusicXmlParseErrorKind::InvalidValue("divisions")));
+            }
+            self.divisions = divisions;
+        }
+        if let Some(time) = child(node, "time") {
+            let beats = self.value::<u8>(time, "beats", "time signature")?;
+            let beat_type = self.value::<u8>(time, "beat-type", "time signature")?;
+            if let (Some(beats), Some(beat_type)) = (beats, beat_type) {
+                self.signature = (beats, beat_type);
+            }
+        }
+        if let Some(details) = child(node, "staff-details") {
+            if let Some(capo) = self.value::<u8>(details, "capo", "capo")? {
+                self.tuning.capo = capo;
+            }
+            let mut strings = vec![];
+            for tuning in details.children().filter(|c| c.has_tag_name("staff-tuning")) {
+                let invalid = || self.error(tuning, MusicXmlParseErrorKind::InvalidValue("staff tuning"));
+                let line = tuning.attribute("line").and_then(|l| l.parse::<u8>().ok()).ok_or_else(invalid)?;
+                let step = child_text(tuning, "tuning-step").and_then(pitch_class).ok_or_else(invalid)?;
+                let alter = self.value::<i32>(tuning, "tuning-alter", "tuning alter")?.unwrap_or(0);
+                let octave = self.value::<i32>(tuning, "tuning-octave", "tuning octave")?.ok_or_else(invalid)?;
+                let pitch = (octave + 1) * 12 + step + alter;
+                strings.push((line, pitch.clamp(0, 127) as u8));
+            }
+            if !strings.is_empty() {
+                // line 1 is the lowest string
+                strings.sort_by_key(|(line, _)| ::std::cmp::Reverse(*line));
+                self.tuning.strings = strings.into_iter().map(|(_, pitch)| pitch).collect();
+            }
+        }
+        Ok(())
+    }
+
+    fn read_barline(&self, node: Node, repeat: &mut Repeat) -> Result<(), MusicXmlParseError> {
+        if let Some(mark) = child(node, "repeat") {
+            match mark.attribute("direction") {
+                Some("forward") => repeat.open = true,
+                Some("backward") => {
+                    let times = match mark.attribute("times") {
+                        Some(times) => times
+                            .parse::<u8>()
+                            .map_err(|_| self.error(mark, MusicXmlParseErrorKind::InvalidValue("repeat times")))?,
+                        None => 2,
+                    };
+                    repeat.close = times.saturating_sub(1);
+                }
+                _ => (),
+            }
+        }
+        if let Some(ending) = child(node, "ending") {
+            if ending.attribute("type") == Some("start") {
+                for number in ending.attribute("number").unwrap_or("").split([',', ' ']) {
+                    if let Ok(number) = number.trim().parse::<u8>() {
+                        if number > 0 && number <= 8 {
+                            repeat.alternative |= 1 << (number - 1);
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read_note(&self, node: Node, start: f64, duration: f64) -> Result<Option<Note>, MusicXmlParseError> {
+        if child(node, "rest").is_some() {
+            return Ok(None);
+        }
+        let notations = match child(node, "notations") {
+            Some(notations) => notations,
+            None => return Ok(None),
+        };
+        let technical = match child(notations, "technical") {
+            Some(technical) => technical,
+            None => return Ok(None),
+        };
+        let (string, fret) = match (
+            self.value::<u8>(technical, "string", "string")?,
+            self.value::<u8>(technical, "fret", "fret")?,
+        ) {
+            (Some(string), Some(fret)) => (string, fret),
+            _ => return Ok(None),
+        };
+        let has = |parent: Node, name: &str, kind: &str| parent.children().any(|c| c.has_tag_name(name) && c.attribute("type") == Some(kind));
+        let bend = match child(technical, "bend") {
+            Some(bend) => self.value::<f64>(bend, "bend-alter", "bend alter")?,
+            None => None,
+        };
+        let ornaments = child(notations, "ornaments");
+        let ornament = |name: &str| ornaments.and_then(|o| child(o, name)).is_some();
+        let harmonic = child(technical, "harmonic").map(|h| match child(h, "artificial") {
+            Some(_) => Harmonic::Pinch,
+            None => Harmonic::Natural,
+        });
+        Ok(Some(Note {
+            start,
+            duration,
+            string,
+            fret,
+            tie: has(node, "tie", "stop") || has(notations, "tied", "stop"),
+            slide: has(notations, "slide", "start") || has(notations, "glissando", "start"),
+            bend: bend.filter(|b| *b > 0.0),
+            techniques: Techniques {
+                // legato marks start on the note before, the note ending them is the one hammered or pulled
+                hammer_on: has(technical, "hammer-on", "stop"),
+                pull_off: has(technical, "pull-off", "stop"),
+                palm_mute: child(technical, "palm-mute").is_some(),
+                harmonic,
+                vibrato: ornament("wavy-line") || ornament("vibrato"),
+                tap: child(technical, "tap").is_some(),
+                tremolo: ornament("tremolo"),
+                dead: child_text(node, "notehead") == Some("x"),
+            },
+        }))
+    }
+
+    fn read_measure(&mut self, node: Node) -> Result<Measure, MusicXmlParseError> {
+        let mut measure = Measure::default();
+        let mut position = 0.0;
+        let mut last_start = 0.0;
+        for element in node.children().filter(|c| c.is_element()) {
+            match element.tag_name().name() {
+                "attributes" => self.read_attributes(element)?,
+                "barline" => self.read_barline(element, &mut measure.repeat)?,
+                "backup" => position -= self.duration(element)?,
+                "forward" => position += self.duration(element)?,
+                "sound" | "direction" => {
+                    for sound in element.descendants().filter(|d| d.has_tag_name("sound")) {
+                        if let Some(tempo) = sound.attribute("tempo") {
+                            let tempo = tempo
+                                .parse::<f64>()
+                                .map_err(|_| self.error(sound, MusicXmlParseErrorKind::InvalidValue("tempo")))?;
+                            measure.tempos.push((position, tempo));
+                        }
+                    }
+                }
+                "note" => {
+                    if child(element, "grace").is_some() {
+                        continue;
+                    }
+                    let duration = self.duration(element)?;
+                    let start = if child(element, "chord").is_some() {
+                        last_start
+                    } else {
+                        last_start = position;
+                        position += duration;
+                        last_start
+                    };
+                    if let Some(note) = self.read_note(element, start, duration)? {
+                        measure.notes.push(note);
+                    }
+                }
+                _ => (),
+            }
+            measure.length = measure.length.max(position);
+        }
+        measure.signature = self.signature;
+        Ok(measure)
+    }
+}
+
+/// parse a partwise musicxml score into one chart per part with tablature,
+/// repeats are expanded and tempo changes applied so that all times are absolute
+pub fn parse_score(content: &str) -> Result<MusicXmlScore, MusicXmlParseError> {
+    let document = Document::parse(content).map_err(|e| MusicXmlParseError {
+        kind: MusicXmlParseErrorKind::Xml(e.to_string()),
+        line: e.pos().row,
+    })?;
+    let root = document.root_element();
+    if !root.has_tag_name("score-partwise") {
+        return Err(MusicXmlParseError {
+            kind: MusicXmlParseErrorKind::UnsupportedDocument(root.tag_name().name().to_string()),
+            line: 1,
+        });
+    }
+    let title = child(root, "work")
+        .and_then(|w| child_text(w, "work-title"))
+        .or_else(|| child_text(root, "movement-title"))
+        .unwrap_or("")
+        .to_string();
+    let artist = child(root, "identification")
+        .and_then(|i| i.children().find(|c| c.has_tag_name("creator")))
+        .and_then(|c| c.text())
+        .unwrap_or("")
+        .trim()
+        .to_string();
+    let names: Vec<(&str, &str)> = child(root, "part-list")
+        .map(|list| {
+            list.children()
+                .filter(|c| c.has_tag_name("score-part"))
+                .map(|p| (p.attribute("id").unwrap_or(""), child_text(p, "part-name").unwrap_or("")))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut parts = vec![];
+    for part in root.children().filter(|c| c.has_tag_name("part")) {
+        let mut parser = Parser {
+            document: &document,
+            divisions: 1.0,
+            signature: (4, 4),
+            // tabs without staff tuning lines are for a guitar in standard tuning
+            tuning: Tuning::standard(),
+        };
+        let mut measures = vec![];
+        for measure in part.children().filter(|c| c.has_tag_name("measure")) {
+            measures.push(parser.read_measure(measure)?);
+        }
+        let id = part.attribute("id").unwrap_or("");
+        let name = names.iter().find(|(i, _)| *i == id).map(|(_, n)| n.to_string()).unwrap_or_default();
+        parts.push((name, parser.tuning, measures));
+    }
+
+    // all parts share repeats and tempo, the first part leads
+    let count = parts.iter().map(|(_, _, m)| m.len()).max().unwrap_or(0);
+    let repeats: Vec<Repeat> = (0..count)
+        .map(|i| parts[0].2.get(i).map(|m| m.repeat.clone()).unwrap_or_default())
+        .collect();
+    let mut order = vec![];
+    let mut tempos = vec![(0.0, DEFAULT_TEMPO)];
+    let mut position = 0.0;
+    for index in playback_order(&repeats) {
+        let mut length: f64 = 0.0;
+        for (_, _, measures) in &parts {
+            if let Some(measure) = measures.get(index) {
+                tempos.extend(measure.tempos.iter().map(|(at, bpm)| (position + at, *bpm)));
+                length = length.max(measure.length);
+            }
+        }
+        order.push((position, index));
+        position += length;
+    }
+    let signatures = time_signatures(
+        order
+            .iter()
+            .map(|(_, index)| parts[0].2.get(*index).map(|m| m.signature).unwrap_or((4, 4))),
+    );
+    let tempo = TempoMap::from_quarters(tempos, signatures);
+
+    let parts = parts
+        .into_iter()
+        .filter(|(_, _, measures)| measures.iter().any(|m| !m.notes.is_empty()))
+        .map(|(name, tuning, measures)| {
+            let mut chart = ChartBuilder::default();
+            for (measure_start, index) in &order {
+                for note in measures.get(*index).map(|m| m.notes.iter()).into_iter().flatten() {
+                    let starts_at = tempo.time_of_quarter(measure_start + note.start);
+                    let ends_at = tempo.time_of_quarter(measure_start + note.start + note.duration);
+                    if note.tie {
+                        chart.tie(note.string, ends_at);
+                    } else {
+                        let fret = Fret {
+                            fret: note.fret,
+                            string: note.string,
+                            starts_at,
+                            ends_at,
+                            techniques: note.techniques.clone(),
+                        };
+                        chart.note(fret, note.bend.map(bend_interval), note.slide);
+                    }
+                }
+            }
+            MusicXmlPart {
+                name,
+                tuning,
+                data: chart.build(tempo.clone()),
+            }
+        })
+        .collect();
+    Ok(MusicXmlScore { title, artist, parts })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::fret;
+    use crate::track::fret_action;
+    use crate::track::hand_position;
+    use crate::track::Action;
+    use crate::track::Interval;
+    use crate::validate::DEFAULT_FRETS;
+
+    #[test]
+    fn test_parse_score() {
+        let score = parse_score(include_str!("../../fixtures/riff.musicxml")).unwrap();
+        assert_eq!("Fixture Song", score.title);
+        assert_eq!("Fixture Band", score.artist);
+        assert_eq!(1, score.parts.len());
+        let part = &score.parts[0];
+        assert_eq!("Guitar", part.name);
+        assert_eq!(Tuning::standard(), part.tuning);
+        assert_eq!(
+            vec![
+                fret_action(0, 500, 5, 3),
+                Action::Fret(Fret {
+                    techniques: Techniques {
+                        hammer_on: true,
+                        ..Techniques::default()
+                    },
+                    ..fret(500, 1000, 7, 3)
+                }),
+                Action::Slide(fret(1000, 1250, 5, 2), fret(1250, 1500, 7, 2)),
+                Action::Bend(fret(1500, 2000, 7, 3), Interval::Step),
+                fret_action(2000, 3000, 0, 1),
+                fret_action(2000, 3000, 1, 2),
+                fret_action(3000, 5000, 3, 4),
+            ],
+            part.data.actions
+        );
+        assert_eq!(vec![hand_position(0, 5), hand_position(2000, 1)], part.data.hand_positions);
+    }
+
+    #[test]
+    fn test_default_tuning() {
+        let content = "<score-partwise>\n<part id=\"P1\">\n<measure>\n<attributes><divisions>1</divisions></attributes>\n\
+                       <note><duration>1</duration><notations><technical><string>6</string><fret>3</fret></technical></notations></note>\n\
+                       </measure>\n</part>\n</score-partwise>";
+        let score = parse_score(content).unwrap();
+        let part = &score.parts[0];
+        assert_eq!(Tuning::standard(), part.tuning);
+        assert_eq!(vec![fret_action(0, 500, 3, 6)], part.data.actions);
+        assert!(part.data.validate(&part.tuning, DEFAULT_FRETS).is_empty());
+    }
+
+    #[test]
+    fn test_parse_score_errors() {
+        let error = parse_score("<score-timewise/>").unwrap_err();
+        assert_eq!(MusicXmlParseErrorKind::UnsupportedDocument("score-timewise".to_string()), error.kind);
+        let content = "<score-partwise>\n<part id=\"P1\">\n<measure>\n<note><duration>x</duration></note>\n</measure>\n</part>\n</score-partwise>";
+        let error = parse_score(content).unwrap_err();
+        assert_eq!((MusicXmlParseErrorKind::InvalidValue("duration"), 4), (error.kind, error.line));
+        assert!(parse_score("<score-partwise><part></score-partwise>").is_err());
+    }
+}
@@ -0,0 +1,591 @@
+use crate::ext::DurationExt;
+use crate::format::chart::bend_interval;
+use crate::format::chart::ChartBuilder;
+use crate::tempo::TempoChange;
+use crate::tempo::TempoMap;
+use crate::tempo::TimeSignature;
+use crate::track::Chord;
+use crate::track::Fret;
+use crate::track::HandPosition;
+use crate::track::Harmonic;
+use crate::track::Level;
+use crate::track::Phrase;
+use crate::track::Section;
+use crate::track::Techniques;
+use crate::track::TrackData;
+use crate::tuning::Tuning;
+use roxmltree::Document;
+use roxmltree::Node;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// rocksmith stores plucked notes without sustain, give them a little length
+const SHORT_NOTE: Duration = Duration::from_millis(100);
+/// attributes of tuning offsets and chord template frets, lowest string first
+const TUNING_STRINGS: [&str; 6] = ["string0", "string1", "string2", "string3", "string4", "string5"];
+const TEMPLATE_FRETS: [&str; 6] = ["fret0", "fret1", "fret2", "fret3", "fret4", "fret5"];
+const TEMPLATE_FINGERS: [&str; 6] = ["finger0", "finger1", "finger2", "finger3", "finger4", "finger5"];
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum RocksmithParseErrorKind {
+    Xml(String),
+    UnsupportedDocument(String),
+    InvalidValue(&'static str),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RocksmithParseError {
+    pub kind: RocksmithParseErrorKind,
+    pub line: u32,
+}
+
+impl fmt::Display for RocksmithParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            RocksmithParseErrorKind::Xml(error) => write!(f, "{}", error),
+            RocksmithParseErrorKind::UnsupportedDocument(root) => write!(f, "unsupported document `{}`, expected song", root),
+            RocksmithParseErrorKind::InvalidValue(what) => write!(f, "line {}: invalid {}", self.line, what),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RocksmithBeat {
+    pub at: Duration,
+    /// number of the measure this beat starts, `None` within a measure
+    pub measure: Option<u32>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RocksmithPhrase {
+    pub name: String,
+    pub starts_at: Duration,
+    pub max_difficulty: u8,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct RocksmithArrangement {
+    pub title: String,
+    pub artist: String,
+    pub arrangement: String,
+    pub tuning: Tuning,
+    pub data: TrackData,
+    pub beats: Vec<RocksmithBeat>,
+    pub phrases: Vec<RocksmithPhrase>,
+}
+
+/// a chord shape, frets and fingers are -1 for strings left out, lowest string first
+struct Template {
+    name: Option<String>,
+    frets: Vec<i8>,
+    fingers: Vec<i8>,
+}
+
+struct Note {
+    /// template of the chord this note is struck in
+    chord: Option<usize>,
+    starts_at: Duration,
+    sustain: Duration,
+    string: u8,
+    fret: u8,
+    slide_to: Option<u8>,
+    bend: Option<f64>,
+    link_next: bool,
+    techniques: Techniques,
+}
+
+struct Parser<'a, 'input> {
+    document: &'a Document<'input>,
+    strings: u8,
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|c| c.has_tag_name(name))
+}
+
+fn children<'a, 'input: 'a>(node: Node<'a, 'input>, list: &'a str, name: &'a str) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    child(node, list)
+        .into_iter()
+        .flat_map(move |l| l.children().filter(move |c| c.has_tag_name(name)))
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> &'a str {
+    child(node, name).and_then(|c| c.text()).map(|t| t.trim()).unwrap_or("")
+}
+
+impl<'a, 'input> Parser<'a, 'input> {
+    fn error(&self, node: Node, what: &'static str) -> RocksmithParseError {
+        RocksmithParseError {
+            kind: RocksmithParseErrorKind::InvalidValue(what),
+            line: self.document.text_pos_at(node.range().start).row,
+        }
+    }
+
+    fn attribute<T: FromStr>(&self, node: Node, name: &'static str) -> Result<Option<T>, RocksmithParseError> {
+        match node.attribute(name) {
+            Some(value) => value.trim().parse::<T>().map(Some).map_err(|_| self.error(node, name)),
+            None => Ok(None),
+        }
+    }
+
+    fn required<T: FromStr>(&self, node: Node, name: &'static str) -> Result<T, RocksmithParseError> {
+        self.attribute(node, name)?.ok_or_else(|| self.error(node, name))
+    }
+
+    fn flag(&self, node: Node, name: &'static str) -> Result<bool, RocksmithParseError> {
+        Ok(self.attribute::<i32>(node, name)?.unwrap_or(0) > 0)
+    }
+
+    fn time(&self, node: Node, name: &'static str) -> Result<Duration, RocksmithParseError> {
+        let seconds: f64 = self.required(node, name)?;
+        Ok(Duration::from_millis((seconds.max(0.0) * 1000.0).round() as u64))
+    }
+
+    /// rocksmith counts strings from the lowest one
+    fn string(&self, node: Node) -> Result<u8, RocksmithParseError> {
+        let string: u8 = self.required(node, "string")?;
+        if string >= self.strings {
+            return Err(self.error(node, "string"));
+        }
+        Ok(self.strings - string)
+    }
+
+    fn read_note(&self, node: Node) -> Result<Note, RocksmithParseError> {
+        let slide_to = self.attribute::<i32>(node, "slideTo")?.filter(|f| *f >= 0).map(|f| f as u8);
+        let bend = children(node, "bendValues", "bendValue")
+            .map(|b| self.attribute::<f64>(b, "step").map(|s| s.unwrap_or(0.0)))
+            .collect::<Result<Vec<f64>, _>>()?
+            .into_iter()
+            .fold(self.attribute::<f64>(node, "bend")?.unwrap_or(0.0), f64::max);
+        Ok(Note {
+            chord: None,
+            starts_at: self.time(node, "time")?,
+            sustain: match node.attribute("sustain") {
+                Some(_) => self.time(node, "sustain")?,
+                None => Duration::from_millis(0),
+            },
+            string: self.string(node)?,
+            fret: self.required(node, "fret")?,
+            slide_to,
+            // bends are measured in whole steps
+            bend: Some(bend * 2.0).filter(|b| *b > 0.0),
+            link_next: self.flag(node, "linkNext")?,
+            techniques: Techniques {
+                hammer_on: self.flag(node, "hammerOn")?,
+                pull_off: self.flag(node, "pullOff")?,
+                palm_mute: self.flag(node, "palmMute")?,
+                harmonic: if self.flag(node, "harmonicPinch")? {
+                    Some(Harmonic::Pinch)
+                } else if self.flag(node, "harmonic")? {
+                    Some(Harmonic::Natural)
+                } else {
+                    None
+                },
+                vibrato: self.flag(node, "vibrato")?,
+                tap: self.flag(node, "tap")?,
+                tremolo: self.flag(node, "tremolo")?,
+                dead: self.flag(node, "mute")?,
+            },
+        })
+    }
+
+    /// chords list their notes when they carry techniques or sustain, otherwise
+    /// the chord template holds the frets
+    fn read_chord(&self, node: Node, templates: &[Template]) -> Result<Vec<Note>, RocksmithParseError> {
+        let id: usize = self.required(node, "chordId")?;
+        let template = templates.get(id).ok_or_else(|| self.error(node, "chordId"))?;
+        let starts_at = self.time(node, "time")?;
+        let notes = node
+            .children()
+            .filter(|c| c.has_tag_name("chordNote"))
+            .map(|n| {
+                let note = self.read_note(n)?;
+                Ok(Note {
+                    chord: Some(id),
+                    starts_at,
+                    ..note
+                })
+            })
+            .collect::<Result<Vec<Note>, _>>()?;
+        if !notes.is_empty() {
+            return Ok(notes);
+        }
+        Ok(template
+            .frets
+            .iter()
+            .enumerate()
+            .filter(|(string, fret)| **fret >= 0 && (*string as u8) < self.strings)
+            .map(|(string, fret)| Note {
+                chord: Some(id),
+                starts_at,
+                sustain: Duration::from_millis(0),
+                string: self.strings - string as u8,
+                fret: *fret as u8,
+                slide_to: None,
+                bend: None,
+                link_next: false,
+                techniques: Techniques::default(),
+            })
+            .collect())
+    }
+}
+
+/// parse a rocksmith 2014 arrangement, every phrase is charted at its highest difficulty
+/// and keeps the lower ones as reduced levels
+pub fn parse_arrangement(content: &str) -> Result<RocksmithArrangement, RocksmithParseError> {
+    let document = Document::parse(content).map_err(|e| RocksmithParseError {
+        kind: RocksmithParseErrorKind::Xml(e.to_string()),
+        line: e.pos().row,
+    })?;
+    let root = document.root_element();
+    if !root.has_tag_name("song") {
+        return Err(RocksmithParseError {
+            kind: RocksmithParseErrorKind::UnsupportedDocument(root.tag_name().name().to_string()),
+            line: 1,
+        });
+    }
+    let arrangement = child_text(root, "arrangement").to_string();
+    let mut tuning = if arrangement.to_lowercase().contains("bass") {
+        Tuning::bass()
+    } else {
+        Tuning::standard()
+    };
+    let parser = Parser {
+        document: &document,
+        strings: tuning.strings.len() as u8,
+    };
+
+    if let Some(node) = child(root, "tuning") {
+        for (index, open) in tuning.strings.iter_mut().rev().enumerate() {
+            let offset = parser.attribute::<i32>(node, TUNING_STRINGS[index])?.unwrap_or(0);
+            *open = (i32::from(*open) + offset).clamp(0, 127) as u8;
+        }
+    }
+    tuning.capo = child_text(root, "capo").parse::<u8>().unwrap_or(0);
+
+    let beats = children(root, "ebeats", "ebeat")
+        .map(|b| {
+            Ok(RocksmithBeat {
+                at: parser.time(b, "time")?,
+                measure: parser.attribute::<i32>(b, "measure")?.filter(|m| *m >= 0).map(|m| m as u32),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let phrase_list = children(root, "phrases", "phrase")
+        .map(|p| {
+            Ok((
+                p.attribute("name").unwrap_or("").to_string(),
+                parser.attribute(p, "maxDifficulty")?.unwrap_or(0),
+            ))
+        })
+        .collect::<Result<Vec<(String, u8)>, _>>()?;
+    let phrases = children(root, "phraseIterations", "phraseIteration")
+        .map(|i| {
+            let id: usize = parser.required(i, "phraseId")?;
+            let (name, max_difficulty) = phrase_list.get(id).cloned().ok_or_else(|| parser.error(i, "phraseId"))?;
+            Ok(RocksmithPhrase {
+                name,
+                starts_at: parser.time(i, "time")?,
+                max_difficulty,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let templates = children(root, "chordTemplates", "chordTemplate")
+        .map(|t| {
+            let strings = |names: &[&'static str; 6]| {
+                names
+                    .iter()
+                    .map(|n| Ok(parser.attribute(t, n)?.unwrap_or(-1)))
+                    .collect::<Result<Vec<i8>, _>>()
+            };
+            let name = t.attribute("displayName").or_else(|| t.attribute("chordName")).unwrap_or("").trim();
+            Ok(Template {
+                name: Some(name.to_string()).filter(|n| !n.is_empty()),
+                frets: strings(&TEMPLATE_FRETS)?,
+                fingers: strings(&TEMPLATE_FINGERS)?,
+            })
+        })
+        .collect::<Result<Vec<Template>, _>>()?;
+
+    let levels: Vec<(u8, Node)> = children(root, "levels", "level")
+        .map(|l| Ok((parser.attribute(l, "difficulty")?.unwrap_or(0), l)))
+        .collect::<Result<_, _>>()?;
+    let hardest = levels.iter().map(|(d, _)| *d).max().unwrap_or(0);
+    // the level every moment of the song is taken from
+    let level_at = |at: &Duration| {
+        let phrase = phrases.iter().rev().find(|p| p.starts_at <= *at);
+        phrase.map(|p| p.max_difficulty.min(hardest)).unwrap_or(hardest)
+    };
+
+    let mut contents: Vec<(u8, Vec<Note>, Vec<HandPosition>)> = vec![];
+    for (difficulty, level) in &levels {
+        let mut level_notes = vec![];
+        for node in children(*level, "notes", "note") {
+            level_notes.push(parser.read_note(node)?);
+        }
+        for node in children(*level, "chords", "chord") {
+            level_notes.extend(parser.read_chord(node, &templates)?);
+        }
+        level_notes.sort_by_key(|n| n.starts_at);
+        let mut anchors = vec![];
+        for node in children(*level, "anchors", "anchor") {
+            anchors.push(HandPosition {
+                fret: parser.required(node, "fret")?,
+                at: parser.time(node, "time")?,
+            });
+        }
+        anchors.sort_by_key(|p| p.at);
+        contents.push((*difficulty, level_notes, anchors));
+    }
+    let mut notes: Vec<&Note> = vec![];
+    let mut hand_positions = vec![];
+    for (difficulty, level_notes, anchors) in &contents {
+        notes.extend(level_notes.iter().filter(|n| level_at(&n.starts_at) == *difficulty));
+        hand_positions.extend(anchors.iter().filter(|p| level_at(&p.at) == *difficulty).cloned());
+    }
+    notes.sort_by_key(|n| n.starts_at);
+    hand_positions.sort_by_key(|p| p.at);
+
+    let mut data = chart(&notes, parser.strings, &templates).build(tempo_map(&beats));
+    data.hand_positions = hand_positions;
+    data.sections = children(root, "sections", "section")
+        .map(|s| {
+            Ok(Section {
+                name: s.attribute("name").unwrap_or("").to_string(),
+                starts_at: parser.time(s, "startTime")?,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let song_end = child_text(root, "songLength")
+        .parse::<f64>()
+        .map(|s| Duration::from_millis((s.max(0.0) * 1000.0).round() as u64))
+        .unwrap_or_default()
+        .max(data.actions.iter().map(|a| *a.ends_at()).max().unwrap_or_default());
+    // lower levels of every phrase become its reduced difficulties
+    for (index, phrase) in phrases.iter().enumerate() {
+        let ends_at = phrases.get(index + 1).map(|p| p.starts_at).unwrap_or(song_end);
+        let inside = |at: &Duration| phrase.starts_at <= *at && *at < ends_at;
+        let top = phrase.max_difficulty.min(hardest);
+        data.phrases.push(Phrase {
+            name: phrase.name.clone(),
+            starts_at: phrase.starts_at,
+            ends_at,
+            levels: contents
+                .iter()
+                .filter(|(difficulty, _, _)| *difficulty < top)
+                .map(|(difficulty, level_notes, anchors)| {
+                    let notes: Vec<&Note> = level_notes.iter().filter(|n| inside(&n.starts_at)).collect();
+                    Level {
+                        difficulty: *difficulty,
+                        actions: chart(&notes, parser.strings, &templates).build(TempoMap::default()).actions,
+                        hand_positions: anchors.iter().filter(|p| inside(&p.at)).cloned().collect(),
+                    }
+                })
+                .collect(),
+        });
+    }
+
+    Ok(RocksmithArrangement {
+        title: child_text(root, "title").to_string(),
+        artist: child_text(root, "artistName").to_string(),
+        arrangement,
+        tuning,
+        data,
+        beats,
+        phrases,
+    })
+}
+
+/// join linked notes, slides and chords of notes sorted by time
+fn chart(notes: &[&Note], strings: u8, templates: &[Template]) -> ChartBuilder {
+    let mut chart = ChartBuilder::default();
+    let mut linked = vec![false; usize::from(strings) + 1];
+    for (index, note) in notes.iter().enumerate() {
+        if let Some(id) = note.chord {
+            let same = |n: &&&Note| n.chord == Some(id) && n.starts_at == note.starts_at;
+            if index > 0 && same(&&notes[index - 1]) {
+                continue;
+            }
+            let members: Vec<&&Note> = notes[index..].iter().take_while(same).collect();
+            let template = &templates[id];
+            let finger = |n: &&&Note| {
+                template
+                    .fingers
+                    .get(usize::from(strings - n.string))
+                    .filter(|f| **f >= 0)
+                    .map(|f| *f as u8)
+            };
+            chart.chord(Chord {
+                name: template.name.clone(),
+                frets: members
+                    .iter()
+                    .map(|n| Fret {
+                        fret: n.fret,
+                        string: n.string,
+                        starts_at: n.starts_at,
+                        ends_at: n.starts_at + n.sustain.max(SHORT_NOTE),
+                        techniques: n.techniques.clone(),
+                    })
+                    .collect(),
+                fingers: members.iter().map(finger).collect(),
+                strum: None,
+            });
+            members.iter().for_each(|n| linked[usize::from(n.string)] = n.link_next);
+            continue;
+        }
+        let ends_at = note.starts_at + note.sustain.max(SHORT_NOTE);
+        let string = usize::from(note.string);
+        if linked[string] {
+            linked[string] = note.link_next;
+            chart.tie(note.string, ends_at);
+            continue;
+        }
+        linked[string] = note.link_next;
+        let fret = Fret {
+            fret: note.fret,
+            string: note.string,
+            starts_at: note.starts_at,
+            ends_at,
+            techniques: note.techniques.clone(),
+        };
+        match note.slide_to {
+            Some(to) => {
+                let middle = note.starts_at + note.sustain.max(SHORT_NOTE) / 2;
+                chart.note(Fret { ends_at: middle, ..fret }, None, true);
+                let to = Fret {
+                    fret: to,
+                    string: note.string,
+                    starts_at: middle,
+                    ends_at,
+                    techniques: Techniques::default(),
+                };
+                chart.note(to, None, false);
+            }
+            None => chart.note(fret, note.bend.map(bend_interval), false),
+        }
+    }
+    chart
+}
+
+/// a tempo change wherever the distance between beats changes, and a time signature
+/// wherever the number of beats in a measure changes
+fn tempo_map(beats: &[RocksmithBeat]) -> TempoMap {
+    let mut tempos: Vec<TempoChange> = vec![];
+    for pair in beats.windows(2) {
+        let length = pair[1].at.total_millis().saturating_sub(pair[0].at.total_millis());
+        if length == 0 {
+            continue;
+        }
+        let bpm = (6_000_000.0 / length as f64).round() / 100.0;
+        match tempos.last() {
+            Some(last) if last.bpm == bpm => (),
+            _ => tempos.push(TempoChange { at: pair[0].at, bpm }),
+        }
+    }
+    let starts: Vec<usize> = beats.iter().enumerate().filter(|(_, b)| b.measure.is_some()).map(|(i, _)| i).collect();
+    let mut time_signatures: Vec<TimeSignature> = vec![];
+    for (measure, pair) in starts.windows(2).enumerate() {
+        let numerator = (pair[1] - pair[0]).min(usize::from(u8::MAX)) as u8;
+        if time_signatures.last().map(|s| s.numerator) != Some(numerator) {
+            time_signatures.push(TimeSignature {
+                measure: measure as u32,
+                numerator,
+                denominator: 4,
+            });
+        }
+    }
+    TempoMap { tempos, time_signatures }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::fret;
+    use crate::track::fret_action;
+    use crate::track::hand_position;
+    use crate::track::Action;
+    use crate::track::Interval;
+
+    #[test]
+    fn test_parse_arrangement() {
+        let arrangement = parse_arrangement(include_str!("../../fixtures/riff.xml")).unwrap();
+        assert_eq!(
+            ("Fixture Song", "Fixture Band", "Lead"),
+            (arrangement.title.as_str(), arrangement.artist.as_str(), arrangement.arrangement.as_str())
+        );
+        assert_eq!(Tuning::drop_d(), arrangement.tuning);
+        assert_eq!(
+            vec![
+                fret_action(0, 500, 5, 3),
+                Action::Fret(Fret {
+                    techniques: Techniques {
+                        dead: true,
+                        ..Techniques::default()
+                    },
+                    ..fret(250, 350, 0, 6)
+                }),
+                Action::Fret(Fret {
+                    techniques: Techniques {
+                        hammer_on: true,
+                        ..Techniques::default()
+                    },
+                    ..fret(500, 1000, 7, 3)
+                }),
+                Action::Slide(fret(1000, 1250, 5, 2), fret(1250, 1500, 7, 2)),
+                Action::Bend(fret(1500, 2000, 7, 3), Interval::Step),
+                Action::Chord(Chord {
+                    name: Some("F".to_string()),
+                    frets: vec![fret(2000, 3000, 1, 2), fret(2000, 3000, 0, 1)],
+                    fingers: vec![Some(1), None],
+                    strum: None,
+                }),
+                fret_action(3000, 5000, 3, 4),
+                Action::Chord(Chord {
+                    name: Some("F".to_string()),
+                    frets: vec![fret(4500, 4600, 1, 2), fret(4500, 4600, 0, 1)],
+                    fingers: vec![Some(1), None],
+                    strum: None,
+                }),
+            ],
+            arrangement.data.actions
+        );
+        assert_eq!(vec![hand_position(0, 5), hand_position(2000, 1)], arrangement.data.hand_positions);
+        assert_eq!(6, arrangement.beats.len());
+        assert_eq!(
+            RocksmithBeat {
+                at: Duration::from_millis(2000),
+                measure: Some(2)
+            },
+            arrangement.beats[4]
+        );
+        let tempos: Vec<(u64, f64)> = arrangement.data.tempo.tempos.iter().map(|t| (t.at.total_millis(), t.bpm)).collect();
+        assert_eq!(vec![(0, 120.0), (2000, 60.0)], tempos);
+        assert_eq!(Duration::from_millis(2000), arrangement.data.tempo.time_of_measure(1));
+        let phrases: Vec<(&str, u64, u8)> = arrangement
+            .phrases
+            .iter()
+            .map(|p| (p.name.as_str(), p.starts_at.as_millis() as u64, p.max_difficulty))
+            .collect();
+        assert_eq!(vec![("intro", 0, 1), ("outro", 2000, 0)], phrases);
+        let sections: Vec<&str> = arrangement.data.sections.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(vec!["intro", "riff"], sections);
+        let intro = &arrangement.data.phrases[0];
+        assert_eq!((Duration::from_millis(0), Duration::from_millis(2000)), (intro.starts_at, intro.ends_at));
+        assert_eq!(1, intro.levels.len());
+        assert_eq!(vec![fret_action(0, 2000, 5, 3)], intro.levels[0].actions);
+        assert_eq!(vec![hand_position(0, 3)], intro.levels[0].hand_positions);
+        let outro = &arrangement.data.phrases[1];
+        assert_eq!((Duration::from_millis(5000), 0), (outro.ends_at, outro.levels.len()));
+    }
+
+    #[test]
+    fn test_parse_arrangement_errors() {
+        let error = parse_arrangement("<score-partwise/>").unwrap_err();
+        assert_eq!(RocksmithParseErrorKind::UnsupportedDocument("score-partwise".to_string()), error.kind);
+        let content =
+            "<song>\n<levels>\n<level difficulty=\"0\">\n<notes>\n<note time=\"0\" fret=\"1\" string=\"6\"/>\n</notes>\n</level>\n</levels>\n</song>";
+        let error = parse_arrangement(content).unwrap_err();
+        assert_eq!((RocksmithParseErrorKind::InvalidValue("string"), 5), (error.kind, error.line));
+    }
+}
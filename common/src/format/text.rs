@@ -0,0 +1,1191 @@
+use crate::ext::DurationExt;
+use crate::tempo::TempoChange;
+use crate::tempo::TempoMap;
+use crate::tempo::TimeSignature;
+use crate::track::hand_position;
+use crate::track::Action;
+use crate::track::Arrangement;
+use crate::track::Chord;
+use crate::track::Fret;
+use crate::track::HandPosition;
+use crate::track::Harmonic;
+use crate::track::Instrument;
+use crate::track::Interval;
+use crate::track::Level;
+use crate::track::Lyric;
+use crate::track::Phrase;
+use crate::track::Section;
+use crate::track::Strum;
+use crate::track::Techniques;
+use crate::track::Track;
+use crate::track::TrackData;
+use crate::tuning::Tuning;
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+const FRET_SHAPE: &str = "fret:<starts_at>:<ends_at>:<fret>:<string>[:+<techniques>]";
+const SLIDE_SHAPE: &str =
+    "slide:<starts_at>:<ends_at>:<fret>:<string>:<to_starts_at>:<to_ends_at>:<to_fret>:<to_string>[:+<techniques>][:+<to_techniques>]";
+const BEND_SHAPE: &str = "bend:<starts_at>:<ends_at>:<fret>:<string>:<interval>[:+<techniques>]";
+const CHORD_SHAPE: &str = "chord:<starts_at>:<ends_at>:<frets>:<strum>[:<name>][:+<techniques>]";
+const HAND_SHAPE: &str = "hand:<at>:<fret>";
+const TEMPO_SHAPE: &str = "tempo:<at>:<bpm>";
+const SIGNATURE_SHAPE: &str = "signature:<measure>:<numerator>:<denominator>";
+const SECTION_SHAPE: &str = "section:<at>:<name>";
+const PHRASE_SHAPE: &str = "phrase:<starts_at>:<ends_at>:<name>";
+const LYRIC_SHAPE: &str = "lyric:<at>:<text>[/]";
+const LEVEL_SHAPE: &str = "level:<difficulty>:<fret, slide, bend, chord or hand line>";
+const TITLE_SHAPE: &str = "title:<text>";
+const ARTIST_SHAPE: &str = "artist:<text>";
+const YOUTUBE_SHAPE: &str = "youtube:<video id>";
+const ARRANGEMENT_SHAPE: &str = "arrangement:<name>";
+const INSTRUMENT_SHAPE: &str = "instrument:<instrument>";
+const TUNING_SHAPE: &str = "tuning:<tuning>";
+const CAPO_SHAPE: &str = "capo:<fret>";
+const OFFSET_SHAPE: &str = "offset:<millis>";
+const LINE_SHAPE: &str =
+    "title:... | artist:... | youtube:... | arrangement:... | instrument:... | tuning:... | capo:... | offset:... | fret:... | slide:... | bend:... | chord:... | hand:... | tempo:... | signature:... | section:... | phrase:... | lyric:... | level:... | # comment";
+
+const FRET_FIELDS: [&str; 4] = ["<starts_at>", "<ends_at>", "<fret>", "<string>"];
+const TO_FRET_FIELDS: [&str; 4] = ["<to_starts_at>", "<to_ends_at>", "<to_fret>", "<to_string>"];
+const INTERVAL_FIELD: &str = "<interval> (half-step | step | double-step)";
+const FRETS_FIELD: &str = "<frets> (comma separated <string>=<fret> with an optional /<finger>, @<starts_at>-<ends_at> and +<technique>)";
+const STRUM_FIELD: &str = "<strum> (down | up | -)";
+const TECHNIQUES_FIELD: &str =
+    "+<techniques> (comma separated hammer-on | pull-off | palm-mute | harmonic | pinch-harmonic | vibrato | tap | tremolo | dead)";
+const INSTRUMENT_FIELD: &str = "<instrument> (guitar | bass)";
+const TUNING_FIELD: &str = "<tuning> (standard | drop-d | e-flat | seven-string | bass | five-string-bass | pitches like E4 B3 G3 D3 A2 E2)";
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum TrackParseErrorKind {
+    UnknownLine,
+    MissingField,
+    UnexpectedField,
+    InvalidValue,
+    DuplicateDirective,
+}
+
+/// parse error pointing at a single token of the text track format,
+/// `line` and `column` are 1-based, `shape` is the full line layout the parser expected
+#[derive(Debug, PartialEq, Clone)]
+pub struct TrackParseError {
+    pub kind: TrackParseErrorKind,
+    pub line: usize,
+    pub column: usize,
+    pub token: String,
+    pub expected: &'static str,
+    pub shape: &'static str,
+}
+
+impl fmt::Display for TrackParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let problem = match self.kind {
+            TrackParseErrorKind::UnknownLine => "unknown line kind",
+            TrackParseErrorKind::MissingField => "missing field",
+            TrackParseErrorKind::UnexpectedField => "unexpected field",
+            TrackParseErrorKind::InvalidValue => "invalid value",
+            TrackParseErrorKind::DuplicateDirective => "duplicate directive",
+        };
+        write!(
+            f,
+            "line {}, column {}: {} `{}`, expected {} in `{}`",
+            self.line, self.column, problem, self.token, self.expected, self.shape
+        )
+    }
+}
+
+/// colon separated fields of a single line, consumed left to right
+struct Fields<'a> {
+    line: usize,
+    content: &'a str,
+    end: usize,
+    shape: &'static str,
+    segments: Vec<(usize, &'a str)>,
+    position: usize,
+}
+
+impl<'a> Fields<'a> {
+    fn new(line: usize, content: &'a str) -> Self {
+        let mut segments = vec![];
+        let mut column = 1;
+        for segment in content.split(':') {
+            let offset = segment.chars().take_while(|c| c.is_whitespace()).count();
+            segments.push((column + offset, segment.trim()));
+            column += segment.chars().count() + 1;
+        }
+        Fields {
+            line,
+            content,
+            end: column,
+            shape: LINE_SHAPE,
+            segments,
+            position: 0,
+        }
+    }
+
+    fn error(&self, kind: TrackParseErrorKind, column: usize, token: &str, expected: &'static str) -> TrackParseError {
+        TrackParseError {
+            kind,
+            line: self.line,
+            column,
+            token: token.to_string(),
+            expected,
+            shape: self.shape,
+        }
+    }
+
+    fn kind(&mut self) -> (usize, &'a str) {
+        let segment = self.segments[self.position];
+        self.position += 1;
+        segment
+    }
+
+    fn next<T: FromStr>(&mut self, expected: &'static str) -> Result<T, TrackParseError> {
+        match self.segments.get(self.position) {
+            Some((column, token)) => {
+                self.position += 1;
+                token
+                    .parse::<T>()
+                    .map_err(|_| self.error(TrackParseErrorKind::InvalidValue, *column, token, expected))
+            }
+            None => Err(self.error(TrackParseErrorKind::MissingField, self.end - 1, "", expected)),
+        }
+    }
+
+    /// takes a trailing techniques field, marked by a leading `+`, off the end of the line
+    fn techniques(&mut self) -> Result<Techniques, TrackParseError> {
+        self.trailing_techniques().map(Option::unwrap_or_default)
+    }
+
+    fn trailing_techniques(&mut self) -> Result<Option<Techniques>, TrackParseError> {
+        match self.segments.last() {
+            Some((column, token)) if self.segments.len() > 1 && token.starts_with('+') => {
+                let techniques = token
+                    .parse()
+                    .map_err(|_| self.error(TrackParseErrorKind::InvalidValue, *column, token, TECHNIQUES_FIELD))?;
+                self.segments.pop();
+                Ok(Some(techniques))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn optional_rest(&mut self, expected: &'static str) -> Result<Option<String>, TrackParseError> {
+        if self.position < self.segments.len() {
+            self.rest(expected).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// takes the remaining fields verbatim, colons included
+    fn rest(&mut self, expected: &'static str) -> Result<String, TrackParseError> {
+        let rest: Vec<&str> = self.content.split(':').take(self.segments.len()).skip(self.position).collect();
+        let rest = rest.join(":");
+        let rest = rest.trim();
+        self.position = self.segments.len();
+        if rest.is_empty() {
+            Err(self.error(TrackParseErrorKind::MissingField, self.end - 1, "", expected))
+        } else {
+            Ok(rest.to_string())
+        }
+    }
+
+    /// rejects the field consumed last, for values that parse but are out of range
+    fn invalid(&self, expected: &'static str) -> TrackParseError {
+        let (column, token) = self.segments[self.position - 1];
+        self.error(TrackParseErrorKind::InvalidValue, column, token, expected)
+    }
+
+    fn finish(&self) -> Result<(), TrackParseError> {
+        match self.segments.get(self.position) {
+            Some((column, token)) => Err(self.error(TrackParseErrorKind::UnexpectedField, *column, token, "end of line")),
+            None => Ok(()),
+        }
+    }
+}
+
+impl FromStr for Interval {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "half-step" => Ok(Interval::HalfStep),
+            "step" => Ok(Interval::Step),
+            "double-step" => Ok(Interval::DoubleStep),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Interval {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Interval::HalfStep => write!(f, "half-step"),
+            Interval::Step => write!(f, "step"),
+            Interval::DoubleStep => write!(f, "double-step"),
+        }
+    }
+}
+
+impl FromStr for Instrument {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "guitar" => Ok(Instrument::Guitar),
+            "bass" => Ok(Instrument::Bass),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Instrument {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Instrument::Guitar => write!(f, "guitar"),
+            Instrument::Bass => write!(f, "bass"),
+        }
+    }
+}
+
+impl FromStr for Strum {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "down" => Ok(Strum::Down),
+            "up" => Ok(Strum::Up),
+            _ => Err(()),
+        }
+    }
+}
+
+impl fmt::Display for Strum {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Strum::Down => write!(f, "down"),
+            Strum::Up => write!(f, "up"),
+        }
+    }
+}
+
+const TECHNIQUE_NAMES: [&str; 9] = [
+    "hammer-on",
+    "pull-off",
+    "palm-mute",
+    "harmonic",
+    "pinch-harmonic",
+    "vibrato",
+    "tap",
+    "tremolo",
+    "dead",
+];
+
+impl Techniques {
+    fn flags(&self) -> [bool; 9] {
+        [
+            self.hammer_on,
+            self.pull_off,
+            self.palm_mute,
+            self.harmonic == Some(Harmonic::Natural),
+            self.harmonic == Some(Harmonic::Pinch),
+            self.vibrato,
+            self.tap,
+            self.tremolo,
+            self.dead,
+        ]
+    }
+}
+
+/// technique names after a `+`, like `+hammer-on,vibrato`
+impl FromStr for Techniques {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut techniques = Techniques::default();
+        let names = s.strip_prefix('+').ok_or(())?;
+        // a bare `+` stands for no techniques, ahead of the ones of a slide target
+        if names.is_empty() {
+            return Ok(techniques);
+        }
+        for name in names.split(',') {
+            match name.trim() {
+                "hammer-on" => techniques.hammer_on = true,
+                "pull-off" => techniques.pull_off = true,
+                "palm-mute" => techniques.palm_mute = true,
+                "harmonic" => techniques.harmonic = Some(Harmonic::Natural),
+                "pinch-harmonic" => techniques.harmonic = Some(Harmonic::Pinch),
+                "vibrato" => techniques.vibrato = true,
+                "tap" => techniques.tap = true,
+                "tremolo" => techniques.tremolo = true,
+                "dead" => techniques.dead = true,
+                _ => return Err(()),
+            }
+        }
+        Ok(techniques)
+    }
+}
+
+impl fmt::Display for Techniques {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = TECHNIQUE_NAMES
+            .iter()
+            .zip(self.flags().iter())
+            .filter(|(_, set)| **set)
+            .map(|(name, _)| *name)
+            .collect();
+        write!(f, "+{}", names.join(","))
+    }
+}
+
+/// a note of a chord, the timing and techniques of the whole chord apply where it has none
+struct ChordNote {
+    string: u8,
+    fret: u8,
+    finger: Option<u8>,
+    timing: Option<(u64, u64)>,
+    techniques: Option<Techniques>,
+}
+
+/// every note of a chord, like `5=3/3,4=2/2@1000-1200,3=0+vibrato+palm-mute`
+struct ChordFrets(Vec<ChordNote>);
+
+impl FromStr for ChordFrets {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut notes: Vec<ChordNote> = vec![];
+        for note in s.split(',') {
+            let (note, techniques) = match note.trim().split_once('+') {
+                Some((note, names)) => (note, Some(format!("+{}", names.replace('+', ",")).parse()?)),
+                None => (note.trim(), None),
+            };
+            let (note, timing) = match note.split_once('@') {
+                Some((note, timing)) => {
+                    let (starts_at, ends_at) = timing.split_once('-').ok_or(())?;
+                    let timing = (starts_at.parse().map_err(|_| ())?, ends_at.parse().map_err(|_| ())?);
+                    (note, Some(timing))
+                }
+                None => (note, None),
+            };
+            let (string, rest) = note.split_once('=').ok_or(())?;
+            let (fret, finger) = match rest.split_once('/') {
+                Some((fret, finger)) => (fret, Some(finger.parse().map_err(|_| ())?)),
+                None => (rest, None),
+            };
+            let string: u8 = string.parse().map_err(|_| ())?;
+            if string == 0 || notes.iter().any(|n| n.string == string) {
+                return Err(());
+            }
+            notes.push(ChordNote {
+                string,
+                fret: fret.parse().map_err(|_| ())?,
+                finger,
+                timing,
+                techniques,
+            });
+        }
+        Ok(ChordFrets(notes))
+    }
+}
+
+fn parse_fret(fields: &mut Fields, names: [&'static str; 4]) -> Result<Fret, TrackParseError> {
+    let starts_at = fields.next(names[0])?;
+    let ends_at = fields.next(names[1])?;
+    let fret = fields.next(names[2])?;
+    let string = fields.next(names[3])?;
+    Ok(Fret {
+        fret,
+        string,
+        starts_at: Duration::from_millis(starts_at),
+        ends_at: Duration::from_millis(ends_at),
+        techniques: Techniques::default(),
+    })
+}
+
+enum Directive {
+    Title(String),
+    Artist(String),
+    Youtube(String),
+    Arrangement(String),
+    Instrument(Instrument),
+    Tuning(Tuning),
+    Capo(u8),
+    Offset(i64),
+}
+
+enum Line {
+    Directive(Directive),
+    Action(Action),
+    HandPosition(HandPosition),
+    Tempo(TempoChange),
+    TimeSignature(TimeSignature),
+    Section(Section),
+    Phrase(Phrase),
+    Lyric(Lyric),
+    /// an action or hand position of a reduced difficulty level
+    Level(u8, Box<Line>),
+}
+
+fn set<T>(slot: &mut Option<T>, value: T, shape: &'static str) -> Result<(), &'static str> {
+    if slot.is_some() {
+        return Err(shape);
+    }
+    *slot = Some(value);
+    Ok(())
+}
+
+/// directives describing the whole song
+#[derive(Default)]
+struct Header {
+    title: Option<String>,
+    artist: Option<String>,
+    youtube: Option<String>,
+    offset: Option<i64>,
+}
+
+/// directives of one arrangement, the first one also takes those before any `arrangement` line
+#[derive(Default)]
+struct ArrangementHeader {
+    name: Option<String>,
+    instrument: Option<Instrument>,
+    tuning: Option<Tuning>,
+    capo: Option<u8>,
+}
+
+/// lines of the chart of one arrangement, levels are kept aside until all phrases are read
+#[derive(Default)]
+struct Chart {
+    actions: Vec<Action>,
+    hand_positions: Vec<HandPosition>,
+    tempo: TempoMap,
+    sections: Vec<Section>,
+    phrases: Vec<Phrase>,
+    lyrics: Vec<Lyric>,
+    leveled: Vec<(u8, Line, TrackParseError)>,
+}
+
+impl Chart {
+    fn is_empty(&self) -> bool {
+        self.actions.is_empty()
+            && self.hand_positions.is_empty()
+            && self.tempo == TempoMap::default()
+            && self.sections.is_empty()
+            && self.phrases.is_empty()
+            && self.lyrics.is_empty()
+            && self.leveled.is_empty()
+    }
+
+    fn finish(self, errors: &mut Vec<TrackParseError>) -> TrackData {
+        let mut phrases = self.phrases;
+        for (difficulty, line, error) in self.leveled {
+            let at = match &line {
+                Line::Action(action) => *action.starts_at(),
+                Line::HandPosition(position) => position.at,
+                _ => continue,
+            };
+            let phrase = match phrases.iter_mut().find(|p| p.contains(at)) {
+                Some(phrase) => phrase,
+                None => {
+                    errors.push(error);
+                    continue;
+                }
+            };
+            let index = match phrase.levels.iter().position(|l| l.difficulty == difficulty) {
+                Some(index) => index,
+                None => {
+                    phrase.levels.push(Level {
+                        difficulty,
+                        actions: vec![],
+                        hand_positions: vec![],
+                    });
+                    phrase.levels.len() - 1
+                }
+            };
+            match line {
+                Line::Action(action) => phrase.levels[index].actions.push(action),
+                Line::HandPosition(position) => phrase.levels[index].hand_positions.push(position),
+                _ => (),
+            }
+        }
+        for phrase in &mut phrases {
+            phrase.levels.sort_by_key(|l| l.difficulty);
+        }
+        TrackData {
+            actions: self.actions,
+            hand_positions: self.hand_positions,
+            tempo: self.tempo,
+            sections: self.sections,
+            phrases,
+            lyrics: self.lyrics,
+        }
+    }
+}
+
+fn parse_line(fields: &mut Fields) -> Result<Line, TrackParseError> {
+    let (column, kind) = fields.kind();
+    match kind {
+        "title" => {
+            fields.shape = TITLE_SHAPE;
+            Ok(Line::Directive(Directive::Title(fields.rest("<text>")?)))
+        }
+        "artist" => {
+            fields.shape = ARTIST_SHAPE;
+            Ok(Line::Directive(Directive::Artist(fields.rest("<text>")?)))
+        }
+        "youtube" => {
+            fields.shape = YOUTUBE_SHAPE;
+            let id = fields.next("<video id>")?;
+            fields.finish()?;
+            Ok(Line::Directive(Directive::Youtube(id)))
+        }
+        "arrangement" => {
+            fields.shape = ARRANGEMENT_SHAPE;
+            Ok(Line::Directive(Directive::Arrangement(fields.rest("<name>")?)))
+        }
+        "instrument" => {
+            fields.shape = INSTRUMENT_SHAPE;
+            let instrument = fields.next(INSTRUMENT_FIELD)?;
+            fields.finish()?;
+            Ok(Line::Directive(Directive::Instrument(instrument)))
+        }
+        "tuning" => {
+            fields.shape = TUNING_SHAPE;
+            let tuning = fields.next(TUNING_FIELD)?;
+            fields.finish()?;
+            Ok(Line::Directive(Directive::Tuning(tuning)))
+        }
+        "capo" => {
+            fields.shape = CAPO_SHAPE;
+            let capo = fields.next("<fret>")?;
+            fields.finish()?;
+            Ok(Line::Directive(Directive::Capo(capo)))
+        }
+        "offset" => {
+            fields.shape = OFFSET_SHAPE;
+            let offset = fields.next("<millis>")?;
+            fields.finish()?;
+            Ok(Line::Directive(Directive::Offset(offset)))
+        }
+        "fret" => {
+            fields.shape = FRET_SHAPE;
+            let techniques = fields.techniques()?;
+            let fret = parse_fret(fields, FRET_FIELDS)?;
+            fields.finish()?;
+            Ok(Line::Action(Action::Fret(Fret { techniques, ..fret })))
+        }
+        "slide" => {
+            fields.shape = SLIDE_SHAPE;
+            // a second techniques field belongs to the slide target
+            let (techniques, to_techniques) = match fields.trailing_techniques()? {
+                Some(last) => match fields.trailing_techniques()? {
+                    Some(first) => (first, last),
+                    None => (last, Techniques::default()),
+                },
+                None => (Techniques::default(), Techniques::default()),
+            };
+            let from = parse_fret(fields, FRET_FIELDS)?;
+            let to = parse_fret(fields, TO_FRET_FIELDS)?;
+            fields.finish()?;
+            Ok(Line::Action(Action::Slide(
+                Fret { techniques, ..from },
+                Fret {
+                    techniques: to_techniques,
+                    ..to
+                },
+            )))
+        }
+        "bend" => {
+            fields.shape = BEND_SHAPE;
+            let techniques = fields.techniques()?;
+            let fret = parse_fret(fields, FRET_FIELDS)?;
+            let interval = fields.next(INTERVAL_FIELD)?;
+            fields.finish()?;
+            Ok(Line::Action(Action::Bend(Fret { techniques, ..fret }, interval)))
+        }
+        "chord" => {
+            fields.shape = CHORD_SHAPE;
+            let techniques = fields.techniques()?;
+            let starts_at = Duration::from_millis(fields.next("<starts_at>")?);
+            let ends_at = Duration::from_millis(fields.next("<ends_at>")?);
+            let ChordFrets(notes) = fields.next(FRETS_FIELD)?;
+            let strum: String = fields.next(STRUM_FIELD)?;
+            let strum = match strum.as_str() {
+                "-" => None,
+                strum => Some(strum.parse().map_err(|_| fields.invalid(STRUM_FIELD))?),
+            };
+            let name = fields.optional_rest("<name>")?.map(unescape_start);
+            let fingers = if notes.iter().any(|note| note.finger.is_some()) {
+                notes.iter().map(|note| note.finger).collect()
+            } else {
+                vec![]
+            };
+            let frets = notes
+                .into_iter()
+                .map(|note| Fret {
+                    fret: note.fret,
+                    string: note.string,
+                    starts_at: note.timing.map_or(starts_at, |(s, _)| Duration::from_millis(s)),
+                    ends_at: note.timing.map_or(ends_at, |(_, e)| Duration::from_millis(e)),
+                    techniques: note.techniques.unwrap_or_else(|| techniques.clone()),
+                })
+                .collect();
+            Ok(Line::Action(Action::Chord(Chord { name, frets, fingers, strum })))
+        }
+        "hand" => {
+            fields.shape = HAND_SHAPE;
+            let at = fields.next("<at>")?;
+            let fret = fields.next("<fret>")?;
+            fields.finish()?;
+            Ok(Line::HandPosition(hand_position(at, fret)))
+        }
+        "tempo" => {
+            fields.shape = TEMPO_SHAPE;
+            let at = fields.next("<at>")?;
+            let bpm: f64 = fields.next("<bpm>")?;
+            if !(bpm.is_finite() && bpm > 0.0) {
+                return Err(fields.invalid("<bpm>"));
+            }
+            fields.finish()?;
+            Ok(Line::Tempo(TempoChange {
+                at: Duration::from_millis(at),
+                bpm,
+            }))
+        }
+        "signature" => {
+            fields.shape = SIGNATURE_SHAPE;
+            let measure = fields.next("<measure>")?;
+            let numerator: u8 = fields.next("<numerator>")?;
+            if numerator == 0 {
+                return Err(fields.invalid("<numerator>"));
+            }
+            let denominator: u8 = fields.next("<denominator>")?;
+            if !denominator.is_power_of_two() {
+                return Err(fields.invalid("<denominator>"));
+            }
+            fields.finish()?;
+            Ok(Line::TimeSignature(TimeSignature {
+                measure,
+                numerator,
+                denominator,
+            }))
+        }
+        "section" => {
+            fields.shape = SECTION_SHAPE;
+            let at = fields.next("<at>")?;
+            let name = fields.rest("<name>")?;
+            Ok(Line::Section(Section {
+                name,
+                starts_at: Duration::from_millis(at),
+            }))
+        }
+        "phrase" => {
+            fields.shape = PHRASE_SHAPE;
+            let starts_at = fields.next("<starts_at>")?;
+            let ends_at: u64 = fields.next("<ends_at>")?;
+            if ends_at < starts_at {
+                return Err(fields.invalid("<ends_at>"));
+            }
+            let name = fields.rest("<name>")?;
+            Ok(Line::Phrase(Phrase {
+                name,
+                starts_at: Duration::from_millis(starts_at),
+                ends_at: Duration::from_millis(ends_at),
+                levels: vec![],
+            }))
+        }
+        "lyric" => {
+            fields.shape = LYRIC_SHAPE;
+            let at = fields.next("<at>")?;
+            let mut text = fields.rest("<text>")?;
+            // a trailing slash not paired with a backslash ends the line of lyrics
+            let line_end = text.ends_with('/') && (text.len() - slashes_start(&text)) % 2 == 1;
+            if line_end {
+                text.pop();
+                text.truncate(text.trim_end().len());
+            }
+            let text = unescape_end(text);
+            if text.is_empty() {
+                let (column, token) = fields.segments[2];
+                return Err(fields.error(TrackParseErrorKind::InvalidValue, column, token, "<text>"));
+            }
+            Ok(Line::Lyric(Lyric {
+                at: Duration::from_millis(at),
+                text,
+                line_end,
+            }))
+        }
+        "level" => {
+            fields.shape = LEVEL_SHAPE;
+            let difficulty = fields.next("<difficulty>")?;
+            if fields.position >= fields.segments.len() {
+                return Err(fields.error(
+                    TrackParseErrorKind::MissingField,
+                    fields.end - 1,
+                    "",
+                    "a fret, slide, bend, chord or hand line",
+                ));
+            }
+            let (inner_column, inner_kind) = fields.segments[fields.position];
+            match parse_line(fields)? {
+                line @ Line::Action(_) | line @ Line::HandPosition(_) => Ok(Line::Level(difficulty, Box::new(line))),
+                _ => {
+                    fields.shape = LEVEL_SHAPE;
+                    Err(fields.error(
+                        TrackParseErrorKind::UnknownLine,
+                        inner_column,
+                        inner_kind,
+                        "a fret, slide, bend, chord or hand line",
+                    ))
+                }
+            }
+        }
+        kind => Err(fields.error(TrackParseErrorKind::UnknownLine, column, kind, "a known line kind")),
+    }
+}
+
+/// the song header and every arrangement with its chart
+type Document = (Header, Vec<(ArrangementHeader, TrackData)>);
+
+/// files written before header directives start with the bare youtube video id
+fn is_video_id(line: &str) -> bool {
+    line.len() == 11 && line.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// with `single` a second `arrangement` line is an error instead of starting another chart
+fn parse_document(content: &str, single: bool) -> Result<Document, Vec<TrackParseError>> {
+    let mut header = Header::default();
+    let mut arrangements: Vec<(ArrangementHeader, Chart)> = vec![(ArrangementHeader::default(), Chart::default())];
+    let mut errors: Vec<TrackParseError> = vec![];
+    let mut first = true;
+    for (index, line) in content.lines().enumerate() {
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let mut fields = Fields::new(index + 1, line.trim_end());
+        let parsed = if first && is_video_id(trimmed) {
+            Ok(Line::Directive(Directive::Youtube(trimmed.to_string())))
+        } else {
+            parse_line(&mut fields)
+        };
+        first = false;
+        let line = match parsed {
+            Ok(line) => line,
+            Err(error) => {
+                errors.push(error);
+                continue;
+            }
+        };
+        if let Line::Directive(Directive::Arrangement(name)) = &line {
+            let taken = arrangements.iter().any(|(h, chart)| match &h.name {
+                Some(taken) => taken == name,
+                // the chart before the first `arrangement` line is the default one once it has content
+                None => !chart.is_empty() && name == Arrangement::DEFAULT_NAME,
+            });
+            if taken {
+                let (column, token) = fields.segments[0];
+                fields.shape = ARRANGEMENT_SHAPE;
+                errors.push(fields.error(
+                    TrackParseErrorKind::DuplicateDirective,
+                    column,
+                    token,
+                    "a name not used by another arrangement",
+                ));
+                continue;
+            }
+        }
+        let (arrangement, chart) = arrangements.last_mut().expect("there is always an arrangement");
+        let song = |set: Result<(), &'static str>| set.err().map(|shape| (shape, "a single directive per file"));
+        let own = |set: Result<(), &'static str>| set.err().map(|shape| (shape, "a single directive per arrangement"));
+        let duplicate = match line {
+            Line::Directive(Directive::Title(title)) => song(set(&mut header.title, title, TITLE_SHAPE)),
+            Line::Directive(Directive::Artist(artist)) => song(set(&mut header.artist, artist, ARTIST_SHAPE)),
+            Line::Directive(Directive::Youtube(youtube)) => song(set(&mut header.youtube, youtube, YOUTUBE_SHAPE)),
+            Line::Directive(Directive::Offset(offset)) => song(set(&mut header.offset, offset, OFFSET_SHAPE)),
+            Line::Directive(Directive::Arrangement(name)) => {
+                // the chart before the first `arrangement` line is named by it
+                if arrangement.name.is_none() && chart.is_empty() {
+                    arrangement.name = Some(name);
+                    None
+                } else if single {
+                    Some((ARRANGEMENT_SHAPE, "a single arrangement per chart"))
+                } else {
+                    let next = ArrangementHeader {
+                        name: Some(name),
+                        ..ArrangementHeader::default()
+                    };
+                    arrangements.push((next, Chart::default()));
+                    None
+                }
+            }
+            Line::Directive(Directive::Instrument(instrument)) => own(set(&mut arrangement.instrument, instrument, INSTRUMENT_SHAPE)),
+            Line::Directive(Directive::Tuning(tuning)) => own(set(&mut arrangement.tuning, tuning, TUNING_SHAPE)),
+            Line::Directive(Directive::Capo(capo)) => own(set(&mut arrangement.capo, capo, CAPO_SHAPE)),
+            Line::Action(action) => {
+                chart.actions.push(action);
+                None
+            }
+            Line::HandPosition(position) => {
+                chart.hand_positions.push(position);
+                None
+            }
+            Line::Tempo(change) => {
+                chart.tempo.tempos.push(change);
+                None
+            }
+            Line::TimeSignature(signature) => {
+                chart.tempo.time_signatures.push(signature);
+                None
+            }
+            Line::Section(section) => {
+                chart.sections.push(section);
+                None
+            }
+            Line::Phrase(phrase) => {
+                chart.phrases.push(phrase);
+                None
+            }
+            Line::Lyric(lyric) => {
+                chart.lyrics.push(lyric);
+                None
+            }
+            Line::Level(difficulty, line) => {
+                // the level belongs to the phrase its first time falls in, known once all phrases are read
+                let (column, token) = fields.segments[3];
+                fields.shape = LEVEL_SHAPE;
+                let error = fields.error(TrackParseErrorKind::InvalidValue, column, token, "a time inside a phrase");
+                chart.leveled.push((difficulty, *line, error));
+                None
+            }
+        };
+        if let Some((shape, expected)) = duplicate {
+            let (column, token) = fields.segments[0];
+            fields.shape = shape;
+            errors.push(fields.error(TrackParseErrorKind::DuplicateDirective, column, token, expected));
+        }
+    }
+    let arrangements: Vec<(ArrangementHeader, TrackData)> = arrangements
+        .into_iter()
+        .map(|(header, chart)| (header, chart.finish(&mut errors)))
+        .collect();
+    errors.sort_by_key(|e| e.line);
+    if errors.is_empty() {
+        Ok((header, arrangements))
+    } else {
+        Err(errors)
+    }
+}
+
+/// parse the chart of a text track with a single arrangement, collecting an error for every
+/// broken line, header directives are checked but otherwise ignored
+pub fn parse_track_data(content: &str) -> Result<TrackData, Vec<TrackParseError>> {
+    parse_document(content, true).map(|(_, mut arrangements)| arrangements.remove(0).1)
+}
+
+/// parse a whole text track file, header directives and the chart of every arrangement,
+/// the returned track has an empty id which is assigned once the track is stored
+pub fn parse_track(content: &str) -> Result<Track, Vec<TrackParseError>> {
+    let (header, arrangements) = parse_document(content, false)?;
+    let arrangements = arrangements
+        .into_iter()
+        .map(|(arrangement, data)| {
+            let instrument = arrangement.instrument.unwrap_or_default();
+            let tuning = arrangement.tuning.unwrap_or_else(|| instrument.standard_tuning());
+            Arrangement {
+                name: arrangement.name.unwrap_or_else(|| Arrangement::DEFAULT_NAME.to_string()),
+                instrument,
+                tuning: tuning.with_capo(arrangement.capo.unwrap_or(0)),
+                data,
+            }
+        })
+        .collect();
+    Ok(Track {
+        id: String::new(),
+        name: header.title.unwrap_or_default(),
+        artist: header.artist,
+        youtube_id: header.youtube.unwrap_or_default(),
+        offset: header.offset.unwrap_or(0),
+        arrangements,
+    })
+}
+
+/// a backslash keeps a chord name starting with `+` from reading as techniques
+fn escape_start(text: &str) -> String {
+    if text.starts_with('+') || text.starts_with('\\') {
+        format!("\\{}", text)
+    } else {
+        text.to_string()
+    }
+}
+
+fn unescape_start(text: String) -> String {
+    match text.strip_prefix('\\') {
+        Some(rest) => rest.to_string(),
+        None => text,
+    }
+}
+
+/// start of the slashes and backslashes ending `text`
+fn slashes_start(text: &str) -> usize {
+    text.trim_end_matches(['/', '\\']).len()
+}
+
+/// lyrics ending in slashes or backslashes write a backslash before each of them,
+/// so a bare trailing `/` only ever marks a line end
+fn escape_end(text: &str) -> String {
+    let start = slashes_start(text);
+    let mut escaped = text[..start].to_string();
+    for c in text[start..].chars() {
+        escaped.push('\\');
+        escaped.push(c);
+    }
+    escaped
+}
+
+fn unescape_end(text: String) -> String {
+    let start = slashes_start(&text);
+    let slashes = &text.as_bytes()[start..];
+    // written in pairs of a backslash and the character it escapes
+    if slashes.chunks(2).all(|pair| pair == [b'\\', pair[pair.len() - 1]]) {
+        let unescaped: String = slashes.chunks(2).map(|pair| char::from(pair[1])).collect();
+        format!("{}{}", &text[..start], unescaped)
+    } else {
+        text
+    }
+}
+
+fn write_fret<W: fmt::Write>(out: &mut W, fret: &Fret) -> fmt::Result {
+    write!(
+        out,
+        "{}:{}:{}:{}",
+        fret.starts_at.total_millis(),
+        fret.ends_at.total_millis(),
+        fret.fret,
+        fret.string
+    )
+}
+
+/// a chord whose notes differ in techniques writes them on each note instead
+fn shares_techniques(chord: &Chord) -> bool {
+    chord.frets.iter().all(|fret| fret.techniques == chord.frets[0].techniques)
+}
+
+/// techniques of the struck fret end the line, followed by the ones of a slide target
+fn write_action<W: fmt::Write>(out: &mut W, action: &Action) -> fmt::Result {
+    write_action_fields(out, action)?;
+    match action {
+        Action::Slide(from, to) if !to.techniques.is_plain() => write!(out, ":{}:{}", from.techniques, to.techniques),
+        Action::Chord(chord) if !shares_techniques(chord) => Ok(()),
+        _ => match action.frets().first() {
+            Some(fret) if !fret.techniques.is_plain() => write!(out, ":{}", fret.techniques),
+            _ => Ok(()),
+        },
+    }
+}
+
+fn write_action_fields<W: fmt::Write>(out: &mut W, action: &Action) -> fmt::Result {
+    match action {
+        Action::Fret(fret) => {
+            write!(out, "fret:")?;
+            write_fret(out, fret)
+        }
+        Action::Slide(from, to) => {
+            write!(out, "slide:")?;
+            write_fret(out, from)?;
+            write!(out, ":")?;
+            write_fret(out, to)
+        }
+        Action::Bend(fret, interval) => {
+            write!(out, "bend:")?;
+            write_fret(out, fret)?;
+            write!(out, ":{}", interval)
+        }
+        Action::Chord(chord) => {
+            // notes timed apart from the chord, from its first start to its last end, carry their own times
+            let (starts_at, ends_at) = (action.starts_at(), action.ends_at());
+            write!(out, "chord:{}:{}:", starts_at.total_millis(), ends_at.total_millis())?;
+            let shared = shares_techniques(chord);
+            for (index, fret) in chord.frets.iter().enumerate() {
+                if index > 0 {
+                    write!(out, ",")?;
+                }
+                write!(out, "{}={}", fret.string, fret.fret)?;
+                if let Some(finger) = chord.finger(index) {
+                    write!(out, "/{}", finger)?;
+                }
+                if fret.starts_at != *starts_at || fret.ends_at != *ends_at {
+                    write!(out, "@{}-{}", fret.starts_at.total_millis(), fret.ends_at.total_millis())?;
+                }
+                if !shared && !fret.techniques.is_plain() {
+                    write!(out, "{}", fret.techniques.to_string().replace(',', "+"))?;
+                }
+            }
+            match &chord.strum {
+                Some(strum) => write!(out, ":{}", strum)?,
+                None => write!(out, ":-")?,
+            }
+            match &chord.name {
+                Some(name) => write!(out, ":{}", escape_start(name)),
+                None => Ok(()),
+            }
+        }
+    }
+}
+
+enum Entry<'a> {
+    Section(&'a Section),
+    Phrase(&'a Phrase),
+    Tempo(&'a TempoChange),
+    HandPosition(&'a HandPosition),
+    Lyric(&'a Lyric),
+    Action(&'a Action),
+    LevelHandPosition(u8, &'a HandPosition),
+    LevelAction(u8, &'a Action),
+}
+
+/// write the canonical text form: time signatures by measure, then one line per entry
+/// ordered by time, sections, phrases, tempo changes, hand positions and lyrics go before actions
+/// starting at the same millisecond, reduced levels follow the full chart
+pub fn write_track_data<W: fmt::Write>(out: &mut W, data: &TrackData) -> fmt::Result {
+    let mut signatures: Vec<&TimeSignature> = data.tempo.time_signatures.iter().collect();
+    signatures.sort_by_key(|s| s.measure);
+    for s in signatures {
+        writeln!(out, "signature:{}:{}:{}", s.measure, s.numerator, s.denominator)?;
+    }
+    let mut entries: Vec<(u64, Entry)> = vec![];
+    entries.extend(data.tempo.tempos.iter().map(|t| (t.at.total_millis(), Entry::Tempo(t))));
+    entries.extend(data.hand_positions.iter().map(|p| (p.at.total_millis(), Entry::HandPosition(p))));
+    entries.extend(data.actions.iter().map(|a| (a.starts_at().total_millis(), Entry::Action(a))));
+    entries.extend(data.sections.iter().map(|s| (s.starts_at.total_millis(), Entry::Section(s))));
+    entries.extend(data.lyrics.iter().map(|l| (l.at.total_millis(), Entry::Lyric(l))));
+    for phrase in &data.phrases {
+        entries.push((phrase.starts_at.total_millis(), Entry::Phrase(phrase)));
+        for level in &phrase.levels {
+            let difficulty = level.difficulty;
+            entries.extend(
+                level
+                    .hand_positions
+                    .iter()
+                    .map(|p| (p.at.total_millis(), Entry::LevelHandPosition(difficulty, p))),
+            );
+            entries.extend(
+                level
+                    .actions
+                    .iter()
+                    .map(|a| (a.starts_at().total_millis(), Entry::LevelAction(difficulty, a))),
+            );
+        }
+    }
+    entries.sort_by_key(|(at, entry)| match entry {
+        Entry::Section(_) => (*at, 0, 0),
+        Entry::Phrase(_) => (*at, 1, 0),
+        Entry::Tempo(_) => (*at, 2, 0),
+        Entry::HandPosition(_) => (*at, 3, 0),
+        Entry::Lyric(_) => (*at, 4, 0),
+        Entry::Action(_) => (*at, 5, 0),
+        Entry::LevelHandPosition(difficulty, _) => (*at, 6, *difficulty),
+        Entry::LevelAction(difficulty, _) => (*at, 7, *difficulty),
+    });
+    for (_, entry) in entries {
+        match entry {
+            Entry::Section(s) => write!(out, "section:{}:{}", s.starts_at.total_millis(), s.name)?,
+            Entry::Phrase(p) => write!(out, "phrase:{}:{}:{}", p.starts_at.total_millis(), p.ends_at.total_millis(), p.name)?,
+            Entry::LevelHandPosition(difficulty, p) => write!(out, "level:{}:hand:{}:{}", difficulty, p.at.total_millis(), p.fret)?,
+            Entry::LevelAction(difficulty, a) => {
+                write!(out, "level:{}:", difficulty)?;
+                write_action(out, a)?
+            }
+            Entry::Tempo(t) => write!(out, "tempo:{}:{}", t.at.total_millis(), t.bpm)?,
+            Entry::HandPosition(p) => write!(out, "hand:{}:{}", p.at.total_millis(), p.fret)?,
+            Entry::Lyric(l) => write!(
+                out,
+                "lyric:{}:{}{}",
+                l.at.total_millis(),
+                escape_end(&l.text),
+                if l.line_end { "/" } else { "" }
+            )?,
+            Entry::Action(a) => write_action(out, a)?,
+        }
+        writeln!(out)?;
+    }
+    Ok(())
+}
+
+impl fmt::Display for TrackData {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_track_data(f, self)
+    }
+}
+
+/// tuning and capo directives of an arrangement that differ from those of its instrument,
+/// `true` when any were written
+fn write_tuning<W: fmt::Write>(out: &mut W, arrangement: &Arrangement) -> Result<bool, fmt::Error> {
+    let mut written = false;
+    if arrangement.tuning.strings != arrangement.instrument.standard_tuning().strings {
+        writeln!(out, "tuning:{}", arrangement.tuning)?;
+        written = true;
+    }
+    if arrangement.tuning.capo != 0 {
+        writeln!(out, "capo:{}", arrangement.tuning.capo)?;
+        written = true;
+    }
+    Ok(written)
+}
+
+/// write header directives for every metadata field that is set, followed by the charts,
+/// a track with a single guitar arrangement of the default name leaves out the `arrangement` line
+pub fn write_track<W: fmt::Write>(out: &mut W, track: &Track) -> fmt::Result {
+    let mut header = false;
+    if !track.name.is_empty() {
+        writeln!(out, "title:{}", track.name)?;
+        header = true;
+    }
+    if let Some(artist) = &track.artist {
+        writeln!(out, "artist:{}", artist)?;
+        header = true;
+    }
+    if !track.youtube_id.is_empty() {
+        writeln!(out, "youtube:{}", track.youtube_id)?;
+        header = true;
+    }
+    let single = match track.arrangements.as_slice() {
+        [arrangement] if arrangement.name == Arrangement::DEFAULT_NAME && arrangement.instrument == Instrument::Guitar => Some(arrangement),
+        _ => None,
+    };
+    if let Some(arrangement) = single {
+        header |= write_tuning(out, arrangement)?;
+    }
+    if track.offset != 0 {
+        writeln!(out, "offset:{}", track.offset)?;
+        header = true;
+    }
+    if let Some(arrangement) = single {
+        if header {
+            writeln!(out)?;
+        }
+        return write_track_data(out, &arrangement.data);
+    }
+    for arrangement in &track.arrangements {
+        if header {
+            writeln!(out)?;
+        }
+        writeln!(out, "arrangement:{}", arrangement.name)?;
+        if arrangement.instrument != Instrument::Guitar {
+            writeln!(out, "instrument:{}", arrangement.instrument)?;
+        }
+        write_tuning(out, arrangement)?;
+        writeln!(out)?;
+        write_track_data(out, &arrangement.data)?;
+        header = true;
+    }
+    Ok(())
+}
+
+impl fmt::Display for Track {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write_track(f, self)
+    }
+}
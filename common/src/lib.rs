@@ -5,13 +5,53 @@ extern crate serde_derive;
 extern crate stdweb;
 
 pub mod ext;
+pub mod fingering;
+pub mod format {
+    pub mod ascii;
+    mod chart;
+    pub mod gp;
+    pub mod lrc;
+    pub mod midi;
+    pub mod musicxml;
+    pub mod rocksmith;
+    pub mod text;
+}
 pub mod note;
+pub mod retune;
+pub mod schema;
+pub mod stats;
+pub mod tempo;
 pub mod track;
+pub mod transform;
+pub mod tuning;
+pub mod validate;
 
 #[cfg(test)]
 mod tests {
+    use crate::format::text::TrackParseErrorKind;
+    use crate::tempo::TempoChange;
+    use crate::tempo::TimeSignature;
+    use crate::track::fret;
     use crate::track::fret_action;
+    use crate::track::hand_position;
+    use crate::track::track_data;
+    use crate::track::Action;
+    use crate::track::Chord;
+    use crate::track::DifficultySelection;
+    use crate::track::Harmonic;
+    use crate::track::Instrument;
+    use crate::track::Interval;
+    use crate::track::Level;
+    use crate::track::Lyric;
+    use crate::track::Phrase;
+    use crate::track::Section;
+    use crate::track::Strum;
+    use crate::track::Techniques;
+    use crate::track::Track;
     use crate::track::TrackData;
+    use crate::track::TrackIndex;
+    use crate::track::Window;
+    use crate::tuning::Tuning;
     use std::time::Duration;
 
     #[test]
@@ -23,16 +63,570 @@ mod tests {
             fret_action(3200, 4200, 10, 3),
             fret_action(4400, 5400, 10, 3),
         ];
-        let hand_positions = vec![];
-        let track = TrackData { actions, hand_positions };
-        assert_eq!(0, track.view(Duration::from_millis(4500)).actions.len());
-        assert_eq!(1, track.view(Duration::from_millis(3300)).actions.len());
-        assert_eq!(2, track.view(Duration::from_millis(2900)).actions.len());
-        assert_eq!(3, track.view(Duration::from_millis(2500)).actions.len());
-        assert_eq!(4, track.view(Duration::from_millis(2100)).actions.len());
-        assert_eq!(5, track.view(Duration::from_millis(0)).actions.len());
+        let track = track_data(actions, vec![]);
+        let index = TrackIndex::new(&track, &DifficultySelection::full());
+        let window = Window::default();
+        let count = |at: u64| index.view(Duration::from_millis(at), &window).actions.len();
+        assert_eq!(0, count(5400));
+        // a note that has started is kept while it still rings
+        assert_eq!(1, count(4500));
+        assert_eq!(2, count(3300));
+        assert_eq!(3, count(2900));
+        assert_eq!(4, count(2500));
+        assert_eq!(5, count(2100));
+        assert_eq!(5, count(0));
+        let short = Window {
+            behind: Duration::from_millis(0),
+            ahead: Duration::from_millis(500),
+        };
+        let starts = |at: u64, window: &Window| -> Vec<u64> {
+            let view = index.view(Duration::from_millis(at), window);
+            view.actions.iter().map(|a| a.starts_at().as_millis() as u64).collect()
+        };
+        assert_eq!(vec![2000, 2400], starts(2100, &short));
+        let behind = Window {
+            behind: Duration::from_millis(300),
+            ..short
+        };
+        assert_eq!(vec![2800, 3200], starts(3100, &behind));
         for action in &track.actions {
-            assert_eq!(true, *action.starts_at() < *action.ends_at())
+            assert!(*action.starts_at() < *action.ends_at())
         }
     }
+
+    fn phrased_track() -> TrackData {
+        let level = |difficulty: u8, actions: Vec<Action>| Level {
+            difficulty,
+            actions,
+            hand_positions: vec![],
+        };
+        TrackData {
+            sections: vec![
+                Section {
+                    name: "intro".to_string(),
+                    starts_at: Duration::from_millis(0),
+                },
+                Section {
+                    name: "verse".to_string(),
+                    starts_at: Duration::from_millis(1000),
+                },
+            ],
+            phrases: vec![Phrase {
+                name: "riff".to_string(),
+                starts_at: Duration::from_millis(1000),
+                ends_at: Duration::from_millis(2000),
+                levels: vec![
+                    level(0, vec![fret_action(1000, 1600, 5, 3)]),
+                    level(1, vec![fret_action(1000, 1200, 5, 3), fret_action(1400, 1600, 8, 3)]),
+                ],
+            }],
+            ..track_data(
+                vec![
+                    fret_action(0, 500, 3, 6),
+                    fret_action(1000, 1200, 5, 3),
+                    fret_action(1200, 1400, 7, 3),
+                    fret_action(1400, 1600, 8, 3),
+                    fret_action(2000, 2500, 5, 2),
+                ],
+                vec![hand_position(0, 3), hand_position(1000, 5)],
+            )
+        }
+    }
+
+    #[test]
+    fn test_view_difficulty() {
+        let track = phrased_track();
+        let starts = |selection: &DifficultySelection| -> Vec<u64> {
+            let index = TrackIndex::new(&track, selection);
+            let view = index.view(Duration::from_millis(0), &Window::default());
+            view.actions.iter().map(|a| a.starts_at().as_millis() as u64).collect()
+        };
+        assert_eq!(vec![0, 1000, 1200, 1400, 2000], starts(&DifficultySelection::full()));
+        assert_eq!(vec![0, 1000, 2000], starts(&DifficultySelection::all(0)));
+        assert_eq!(vec![0, 1000, 1400, 2000], starts(&DifficultySelection::full().with_phrase(0, 1)));
+        // the full chart sits one above the hardest reduced level
+        assert_eq!(vec![0, 1000, 1200, 1400, 2000], starts(&DifficultySelection::all(0).with_phrase(0, 2)));
+        assert_eq!(2, track.phrases[0].max_difficulty());
+        // the hand position in effect comes first
+        let index = TrackIndex::new(&track, &DifficultySelection::full());
+        let view = index.view(Duration::from_millis(1200), &Window::default());
+        assert_eq!(vec![5], view.hand_positions.iter().map(|p| p.fret).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn test_phrase_text() {
+        let track = phrased_track();
+        let text = track.to_string();
+        assert_eq!(
+            "section:0:intro\n\
+             hand:0:3\n\
+             fret:0:500:3:6\n\
+             section:1000:verse\n\
+             phrase:1000:2000:riff\n\
+             hand:1000:5\n\
+             fret:1000:1200:5:3\n\
+             level:0:fret:1000:1600:5:3\n\
+             level:1:fret:1000:1200:5:3\n\
+             fret:1200:1400:7:3\n\
+             fret:1400:1600:8:3\n\
+             level:1:fret:1400:1600:8:3\n\
+             fret:2000:2500:5:2\n",
+            text
+        );
+        assert_eq!(track, TrackData::parse(&text).unwrap());
+        let errors = TrackData::parse("phrase:1000:2000:riff\nlevel:0:fret:2500:2600:5:3\nlevel:0:title:x\nphrase:10:5:x").unwrap_err();
+        let positions: Vec<_> = errors.iter().map(|e| (e.kind.clone(), e.line, e.column, e.expected)).collect();
+        assert_eq!(
+            vec![
+                (TrackParseErrorKind::InvalidValue, 2, 14, "a time inside a phrase"),
+                (TrackParseErrorKind::UnknownLine, 3, 9, "a fret, slide, bend, chord or hand line"),
+                (TrackParseErrorKind::InvalidValue, 4, 11, "<ends_at>"),
+            ],
+            positions
+        );
+    }
+
+    #[test]
+    fn test_parse() {
+        let track = TrackData::parse("hand:3600:7\n\nfret:5113:5213:10:3\r\n fret : 5431:5531:10:3 \n").unwrap();
+        assert_eq!(vec![hand_position(3600, 7)], track.hand_positions);
+        assert_eq!(vec![fret_action(5113, 5213, 10, 3), fret_action(5431, 5531, 10, 3)], track.actions);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let errors = TrackData::parse("fret:1:2:3:4\nfret:1:2\nhand:10:x\nfert:1:2:3:4\nhand:1:2:3").unwrap_err();
+        let positions: Vec<_> = errors
+            .iter()
+            .map(|e| (e.kind.clone(), e.line, e.column, e.token.as_str(), e.expected))
+            .collect();
+        assert_eq!(
+            vec![
+                (TrackParseErrorKind::MissingField, 2, 9, "", "<fret>"),
+                (TrackParseErrorKind::InvalidValue, 3, 9, "x", "<fret>"),
+                (TrackParseErrorKind::UnknownLine, 4, 1, "fert", "a known line kind"),
+                (TrackParseErrorKind::UnexpectedField, 5, 10, "3", "end of line"),
+            ],
+            positions
+        );
+        assert_eq!(
+            "line 3, column 9: invalid value `x`, expected <fret> in `hand:<at>:<fret>`",
+            errors[1].to_string()
+        );
+    }
+
+    #[test]
+    fn test_parse_slide_and_bend() {
+        let content = "slide:1000:1200:5:3:1200:1500:7:3\n\
+                       bend:2000:2500:7:2:half-step\n\
+                       bend:3000:3500:7:2:step\n\
+                       bend:4000:4500:7:2:double-step\n";
+        let track = TrackData::parse(content).unwrap();
+        let expected = vec![
+            Action::Slide(fret(1000, 1200, 5, 3), fret(1200, 1500, 7, 3)),
+            Action::Bend(fret(2000, 2500, 7, 2), Interval::HalfStep),
+            Action::Bend(fret(3000, 3500, 7, 2), Interval::Step),
+            Action::Bend(fret(4000, 4500, 7, 2), Interval::DoubleStep),
+        ];
+        assert_eq!(expected, track.actions);
+        let json = serde_json::to_string(&track).unwrap();
+        assert_eq!(track, serde_json::from_str::<TrackData>(&json).unwrap());
+    }
+
+    #[test]
+    fn test_parse_chord() {
+        let content = "chord:1000:1500:5=3/3,4=2/2,3=0,2=1/1,1=0:down:C\n\
+                       chord:2000:2500:6=3,5=2,1=3:-\n";
+        let track = TrackData::parse(content).unwrap();
+        let expected = vec![
+            Action::Chord(Chord {
+                name: Some("C".to_string()),
+                frets: vec![
+                    fret(1000, 1500, 3, 5),
+                    fret(1000, 1500, 2, 4),
+                    fret(1000, 1500, 0, 3),
+                    fret(1000, 1500, 1, 2),
+                    fret(1000, 1500, 0, 1),
+                ],
+                fingers: vec![Some(3), Some(2), None, Some(1), None],
+                strum: Some(Strum::Down),
+            }),
+            Action::Chord(Chord {
+                name: None,
+                frets: vec![fret(2000, 2500, 3, 6), fret(2000, 2500, 2, 5), fret(2000, 2500, 3, 1)],
+                fingers: vec![],
+                strum: None,
+            }),
+        ];
+        assert_eq!(expected, track.actions);
+        assert_eq!(Duration::from_millis(2500), *track.actions[1].ends_at());
+        assert_eq!(content, track.to_string());
+        let json = serde_json::to_string(&track).unwrap();
+        assert_eq!(track, serde_json::from_str::<TrackData>(&json).unwrap());
+        let errors = TrackData::parse("chord:0:500:5=3,5=2:down\nchord:0:500:5=3:sideways\nchord:0:500::up").unwrap_err();
+        let positions: Vec<_> = errors.iter().map(|e| (e.line, e.column, e.token.as_str())).collect();
+        assert_eq!(vec![(1, 13, "5=3,5=2"), (2, 17, "sideways"), (3, 13, "")], positions);
+    }
+
+    #[test]
+    fn test_parse_techniques() {
+        let content = "fret:1000:1200:5:3\n\
+                       fret:1200:1400:7:3:+hammer-on,vibrato\n\
+                       slide:1400:1600:7:3:1600:1800:5:3:+pull-off\n\
+                       bend:2000:2500:7:2:step:+pinch-harmonic,tremolo\n\
+                       chord:3000:3200:6=0,5=0:down:+palm-mute,dead\n\
+                       fret:4000:4200:12:1:+harmonic,tap\n";
+        let track = TrackData::parse(content).unwrap();
+        let techniques: Vec<Techniques> = track.actions.iter().map(|a| a.frets()[0].techniques.clone()).collect();
+        assert!(techniques[0].is_plain());
+        assert!(techniques[1].hammer_on && techniques[1].vibrato);
+        assert!(techniques[2].pull_off);
+        assert_eq!(Some(Harmonic::Pinch), techniques[3].harmonic);
+        assert!(techniques[3].tremolo);
+        assert!(track.actions[4].frets().iter().all(|f| f.techniques.palm_mute && f.techniques.dead));
+        assert_eq!((Some(Harmonic::Natural), true), (techniques[5].harmonic.clone(), techniques[5].tap));
+        assert_eq!(content, track.to_string());
+
+        let mut stored = Track::parse(content).unwrap();
+        stored.id = "1".to_string();
+        let json = serde_json::to_string(&stored).unwrap();
+        assert_eq!(stored, serde_json::from_str::<Track>(&json).unwrap());
+        // charts stored before techniques existed still load
+        let old =
+            r#"{"actions":[{"Fret":{"fret":5,"string":3,"ends_at":{"secs":1,"nanos":0},"starts_at":{"secs":0,"nanos":0}}}],"hand_positions":[]}"#;
+        assert!(serde_json::from_str::<TrackData>(old).unwrap().actions[0].frets()[0]
+            .techniques
+            .is_plain());
+
+        let errors = TrackData::parse("fret:0:100:5:3:+hammer-on,sweep").unwrap_err();
+        assert_eq!(
+            (TrackParseErrorKind::InvalidValue, 16, "+hammer-on,sweep"),
+            (errors[0].kind.clone(), errors[0].column, errors[0].token.as_str())
+        );
+    }
+
+    #[test]
+    fn test_chord_text_round_trip() {
+        let mut hammered = fret(1000, 1400, 5, 3);
+        hammered.techniques.hammer_on = true;
+        hammered.techniques.vibrato = true;
+        let mut target = fret(2200, 2400, 7, 3);
+        target.techniques.vibrato = true;
+        let data = TrackData {
+            sections: vec![Section {
+                name: "Verse: 1".to_string(),
+                starts_at: Duration::from_millis(0),
+            }],
+            ..track_data(
+                vec![
+                    Action::Chord(Chord {
+                        name: Some("A5: power".to_string()),
+                        frets: vec![fret(1000, 1500, 0, 5), fret(1100, 1500, 2, 4), hammered],
+                        fingers: vec![None, Some(1), Some(2)],
+                        strum: Some(Strum::Up),
+                    }),
+                    Action::Slide(fret(2000, 2200, 5, 3), target),
+                ],
+                vec![],
+            )
+        };
+        let text = data.to_string();
+        assert_eq!(
+            "section:0:Verse: 1\n\
+             chord:1000:1500:5=0,4=2/1@1100-1500,3=5/2@1000-1400+hammer-on+vibrato:up:A5: power\n\
+             slide:2000:2200:5:3:2200:2400:7:3:+:+vibrato\n",
+            text
+        );
+        assert_eq!(data, TrackData::parse(&text).unwrap());
+    }
+
+    #[test]
+    fn test_parse_slide_and_bend_errors() {
+        let errors = TrackData::parse("slide:1000:1200:5:3:1200\nbend:2000:2500:7:2:whole").unwrap_err();
+        assert_eq!(2, errors.len());
+        assert_eq!(
+            (TrackParseErrorKind::MissingField, "<to_ends_at>"),
+            (errors[0].kind.clone(), errors[0].expected)
+        );
+        assert_eq!(
+            (TrackParseErrorKind::InvalidValue, 2, 20, "whole"),
+            (errors[1].kind.clone(), errors[1].line, errors[1].column, errors[1].token.as_str())
+        );
+    }
+
+    #[test]
+    fn test_to_text() {
+        let track = track_data(
+            vec![
+                Action::Bend(fret(3000, 3500, 7, 2), Interval::Step),
+                fret_action(1000, 1200, 5, 3),
+                Action::Slide(fret(2000, 2200, 5, 3), fret(2200, 2500, 7, 3)),
+            ],
+            vec![hand_position(2000, 5), hand_position(0, 3)],
+        );
+        let text = track.to_string();
+        assert_eq!(
+            "hand:0:3\n\
+             fret:1000:1200:5:3\n\
+             hand:2000:5\n\
+             slide:2000:2200:5:3:2200:2500:7:3\n\
+             bend:3000:3500:7:2:step\n",
+            text
+        );
+        let parsed = TrackData::parse(&text).unwrap();
+        assert_eq!(text, parsed.to_string());
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let track = track_data(
+            vec![
+                fret_action(1000, 1200, 5, 3),
+                Action::Slide(fret(2000, 2200, 5, 3), fret(2200, 2500, 7, 3)),
+                Action::Bend(fret(3000, 3500, 7, 2), Interval::HalfStep),
+                Action::Bend(fret(4000, 4500, 7, 2), Interval::DoubleStep),
+            ],
+            vec![hand_position(0, 3), hand_position(2000, 5)],
+        );
+        assert_eq!(track, TrackData::parse(&track.to_string()).unwrap());
+    }
+
+    #[test]
+    fn test_text_escapes() {
+        let chord = |at: u64, name: &str| {
+            Action::Chord(Chord {
+                name: Some(name.to_string()),
+                frets: vec![fret(at, at + 500, 0, 5)],
+                fingers: vec![],
+                strum: None,
+            })
+        };
+        let lyric = |at: u64, text: &str, line_end: bool| Lyric {
+            at: Duration::from_millis(at),
+            text: text.to_string(),
+            line_end,
+        };
+        let track = TrackData {
+            lyrics: vec![
+                lyric(0, "and/", false),
+                lyric(500, "or/", true),
+                lyric(1000, "back\\", true),
+                lyric(1500, "slash\\/", false),
+            ],
+            ..track_data(vec![chord(0, "+5"), chord(1000, "\\m7")], vec![])
+        };
+        let text = track.to_string();
+        assert!(text.contains("chord:0:500:5=0:-:\\+5\n"));
+        assert!(text.contains("lyric:0:and\\/\n"));
+        assert!(text.contains("lyric:500:or\\//\n"));
+        assert_eq!(track, TrackData::parse(&text).unwrap());
+
+        // columns count characters, also past whitespace outside ascii
+        let errors = TrackData::parse("section:\u{a0}x:intro\nlyric:0:été:\u{a0}\nfret:\u{a0}é:500:5:3").unwrap_err();
+        let columns: Vec<(usize, usize)> = errors.iter().map(|e| (e.line, e.column)).collect();
+        assert_eq!(vec![(1, 10), (3, 7)], columns);
+    }
+
+    #[test]
+    fn test_tempo_text() {
+        let content = "signature:0:4:4\n\
+                       signature:2:6:8\n\
+                       tempo:0:120\n\
+                       fret:0:500:5:3\n\
+                       tempo:4000:92.5\n\
+                       hand:4000:7\n";
+        let track = TrackData::parse(content).unwrap();
+        assert_eq!(
+            vec![
+                TempoChange {
+                    at: Duration::from_millis(0),
+                    bpm: 120.0
+                },
+                TempoChange {
+                    at: Duration::from_millis(4000),
+                    bpm: 92.5
+                },
+            ],
+            track.tempo.tempos
+        );
+        assert_eq!(
+            TimeSignature {
+                measure: 2,
+                numerator: 6,
+                denominator: 8
+            },
+            track.tempo.time_signature_at(3)
+        );
+        assert_eq!(content, track.to_string());
+        let errors = TrackData::parse(
+            "tempo:0:0
+signature:1:3:5
+tempo:100",
+        )
+        .unwrap_err();
+        let positions: Vec<_> = errors.iter().map(|e| (e.kind.clone(), e.line, e.expected)).collect();
+        assert_eq!(
+            vec![
+                (TrackParseErrorKind::InvalidValue, 1, "<bpm>"),
+                (TrackParseErrorKind::InvalidValue, 2, "<denominator>"),
+                (TrackParseErrorKind::MissingField, 3, "<bpm>"),
+            ],
+            positions
+        );
+    }
+
+    #[test]
+    fn test_lyrics_text() {
+        let content = "lyric:900:Is this the real life/\n\
+                       fret:1000:1200:5:3\n\
+                       lyric:2000:Is this just fan-\n\
+                       lyric:2500:ta-\n\
+                       lyric:2700:sy: or not/\n";
+        let track = TrackData::parse(content).unwrap();
+        assert_eq!(
+            Lyric {
+                at: Duration::from_millis(2700),
+                text: "sy: or not".to_string(),
+                line_end: true,
+            },
+            track.lyrics[3]
+        );
+        assert_eq!(content, track.to_string());
+        let lines: Vec<(u64, String)> = track
+            .lyric_lines()
+            .into_iter()
+            .map(|l| (l.starts_at.as_millis() as u64, l.text))
+            .collect();
+        assert_eq!(
+            vec![
+                (900, "Is this the real life".to_string()),
+                (2000, "Is this just fantasy: or not".to_string())
+            ],
+            lines
+        );
+        // the line being sung comes first
+        let index = TrackIndex::new(&track, &DifficultySelection::full());
+        let view = index.view(Duration::from_millis(1500), &Window::default());
+        assert_eq!(
+            vec![900, 2000],
+            view.lyrics.iter().map(|l| l.starts_at.as_millis() as u64).collect::<Vec<_>>()
+        );
+
+        let errors = TrackData::parse("lyric:soon:la\nlyric:100\nlyric:100:/").unwrap_err();
+        let kinds: Vec<_> = errors.iter().map(|e| (e.kind.clone(), e.line)).collect();
+        assert_eq!(
+            vec![
+                (TrackParseErrorKind::InvalidValue, 1),
+                (TrackParseErrorKind::MissingField, 2),
+                (TrackParseErrorKind::InvalidValue, 3),
+            ],
+            kinds
+        );
+    }
+
+    #[test]
+    fn test_parse_track_header() {
+        // the old layout starts with the bare video id
+        let track = Track::parse(include_str!("../../rhapsody.txt")).unwrap();
+        assert_eq!("4MkcQay5mF8", track.youtube_id);
+        assert_eq!(Some(&hand_position(3600, 7)), track.arrangements[0].data.hand_positions.first());
+        assert_eq!(Some(&fret_action(5113, 5213, 10, 3)), track.arrangements[0].data.actions.first());
+        let content = format!("title:Bohemian Rhapsody\nartist:Queen\n{}", include_str!("../../rhapsody.txt"));
+        let errors = Track::parse(&content).unwrap_err();
+        assert_eq!(TrackParseErrorKind::UnknownLine, errors[0].kind);
+        let content = format!(
+            "# Queen\ntitle:Bohemian Rhapsody\nartist:Queen\nyoutube:{}",
+            include_str!("../../rhapsody.txt")
+        );
+        let track = Track::parse(&content).unwrap();
+        assert_eq!(
+            ("Bohemian Rhapsody", Some("Queen".to_string())),
+            (track.name.as_str(), track.artist.clone())
+        );
+        assert_eq!("4MkcQay5mF8", track.youtube_id);
+    }
+
+    #[test]
+    fn test_track_text_round_trip() {
+        let content = "# comments are skipped\n\
+                       title:Song: Live\n\
+                       artist:Band\n\
+                       youtube:abc\n\
+                       tuning:drop-d\n\
+                       capo:2\n\
+                       offset:-250\n\
+                       \n\
+                       hand:0:3\n\
+                       fret:1000:1200:5:3\n";
+        let track = Track::parse(content).unwrap();
+        assert_eq!("Song: Live", track.name);
+        assert_eq!(Tuning::drop_d().with_capo(2), track.arrangements[0].tuning);
+        assert_eq!(-250, track.offset);
+        assert_eq!(&content[content.find('\n').unwrap() + 1..], track.to_string());
+        assert_eq!(track.arrangements[0].data, TrackData::parse(content).unwrap());
+    }
+
+    #[test]
+    fn test_arrangements_text() {
+        let content = "title:Song\n\
+                       \n\
+                       arrangement:Rhythm\n\
+                       tuning:drop-d\n\
+                       \n\
+                       fret:0:500:0:6\n\
+                       \n\
+                       arrangement:Bass\n\
+                       instrument:bass\n\
+                       capo:1\n\
+                       \n\
+                       fret:0:500:2:4\n";
+        let track = Track::parse(content).unwrap();
+        let names: Vec<&str> = track.arrangements.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(vec!["Rhythm", "Bass"], names);
+        assert_eq!(Tuning::drop_d(), track.arrangements[0].tuning);
+        assert_eq!(Instrument::Bass, track.arrangements[1].instrument);
+        assert_eq!(Tuning::bass().with_capo(1), track.arrangements[1].tuning);
+        assert_eq!(vec![fret_action(0, 500, 2, 4)], track.arrangements[1].data.actions);
+        assert_eq!(content, track.to_string());
+        // the chart before the first arrangement line is the lead
+        let track = Track::parse("fret:0:500:5:3\narrangement:Bass\ninstrument:bass\nfret:0:500:2:4").unwrap();
+        assert_eq!("Lead", track.arrangements[0].name);
+        assert_eq!(2, track.arrangements.len());
+        let errors = Track::parse("fret:0:500:5:3\narrangement:Lead\nfret:500:1000:7:3").unwrap_err();
+        assert_eq!((TrackParseErrorKind::DuplicateDirective, 2), (errors[0].kind.clone(), errors[0].line));
+        assert_eq!(1, Track::parse("arrangement:Lead\nfret:0:500:5:3").unwrap().arrangements.len());
+        // a single chart can't hold more than one arrangement
+        let errors = TrackData::parse(content).unwrap_err();
+        assert_eq!((TrackParseErrorKind::DuplicateDirective, 8), (errors[0].kind.clone(), errors[0].line));
+        assert_eq!(
+            vec![fret_action(0, 500, 5, 3)],
+            TrackData::parse("arrangement:Lead\nfret:0:500:5:3").unwrap().actions
+        );
+
+        let errors = Track::parse("arrangement:Bass\ninstrument:bass\ninstrument:guitar\narrangement:Bass\ninstrument:drums").unwrap_err();
+        let kinds: Vec<_> = errors.iter().map(|e| (e.kind.clone(), e.line)).collect();
+        assert_eq!(
+            vec![
+                (TrackParseErrorKind::DuplicateDirective, 3),
+                (TrackParseErrorKind::DuplicateDirective, 4),
+                (TrackParseErrorKind::InvalidValue, 5),
+            ],
+            kinds
+        );
+    }
+
+    #[test]
+    fn test_parse_track_header_errors() {
+        let errors = Track::parse("title:One\ntitle:Two\nartist:\noffset:soon\ntuning:H2 A2").unwrap_err();
+        let kinds: Vec<_> = errors.iter().map(|e| (e.kind.clone(), e.line)).collect();
+        assert_eq!(
+            vec![
+                (TrackParseErrorKind::DuplicateDirective, 2),
+                (TrackParseErrorKind::MissingField, 3),
+                (TrackParseErrorKind::InvalidValue, 4),
+                (TrackParseErrorKind::InvalidValue, 5),
+            ],
+            kinds
+        );
+    }
 }
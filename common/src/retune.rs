@@ -0,0 +1,254 @@
+use crate::fingering::plan_hand;
+use crate::track::Action;
+use crate::track::Arrangement;
+use crate::track::Chord;
+use crate::track::Fret;
+use crate::track::TrackData;
+use crate::tuning::Tuning;
+use crate::validate::DEFAULT_FRETS;
+
+/// a note of the original chart with no place on the target instrument,
+/// `pitch` is what it should sound like, `None` when that isn't a midi pitch
+#[derive(PartialEq, Clone, Debug)]
+pub struct Unplayable {
+    pub fret: Fret,
+    pub pitch: Option<u8>,
+}
+
+struct Refret<'a> {
+    from: &'a Tuning,
+    to: &'a Tuning,
+    semitones: i16,
+    /// last fret above the capo of the target instrument
+    frets: u8,
+    unplayable: Vec<Unplayable>,
+}
+
+impl<'a> Refret<'a> {
+    fn pitch(&self, fret: &Fret) -> Option<u8> {
+        let pitch = i16::from(self.from.pitch_of(fret)?) + self.semitones;
+        if (0..=127).contains(&pitch) {
+            Some(pitch as u8)
+        } else {
+            None
+        }
+    }
+
+    /// the position playing the pitch of `fret` on a string not in `used`,
+    /// keeping the string if it can and staying close to the original fret otherwise
+    fn place(&self, fret: &Fret, pitch: Option<u8>, used: &[u8]) -> Option<Fret> {
+        let pitch = pitch?;
+        (1..=self.to.strings.len() as u8)
+            .filter(|string| !used.contains(string))
+            .filter_map(|string| Some((string, self.to.fret_for(string, pitch).filter(|f| *f <= self.frets)?)))
+            .min_by_key(|(string, f)| (*string != fret.string, (i16::from(*f) - i16::from(fret.fret)).abs(), *string))
+            .map(|(string, f)| Fret {
+                fret: f,
+                string,
+                ..fret.clone()
+            })
+    }
+
+    fn single(&mut self, fret: &Fret) -> Option<Fret> {
+        let pitch = self.pitch(fret);
+        let placed = self.place(fret, pitch, &[]);
+        if placed.is_none() {
+            self.unplayable.push(Unplayable { fret: fret.clone(), pitch });
+        }
+        placed
+    }
+
+    fn action(&mut self, action: &Action) -> Option<Action> {
+        match action {
+            Action::Fret(f) => self.single(f).map(Action::Fret),
+            Action::Bend(f, interval) => self.single(f).map(|f| Action::Bend(f, interval.clone())),
+            Action::Slide(f1, f2) => {
+                let (p1, p2) = (self.pitch(f1), self.pitch(f2));
+                // both ends of a slide stay on one string
+                let slide = self.place(f1, p1, &[]).and_then(|start| {
+                    let end = p2.and_then(|p| self.to.fret_for(start.string, p)).filter(|f| *f <= self.frets)?;
+                    Some(Action::Slide(
+                        start.clone(),
+                        Fret {
+                            fret: end,
+                            string: start.string,
+                            ..f2.clone()
+                        },
+                    ))
+                });
+                if slide.is_none() {
+                    self.unplayable.push(Unplayable { fret: f1.clone(), pitch: p1 });
+                    self.unplayable.push(Unplayable { fret: f2.clone(), pitch: p2 });
+                }
+                slide
+            }
+            Action::Chord(chord) => {
+                let mut frets: Vec<Fret> = vec![];
+                let mut fingers = vec![];
+                // index in the chord of every placed fret, unplayable ones leave gaps
+                let mut placed_from = vec![];
+                for (index, fret) in chord.frets.iter().enumerate() {
+                    let pitch = self.pitch(fret);
+                    let used: Vec<u8> = frets.iter().map(|f| f.string).collect();
+                    match self.place(fret, pitch, &used) {
+                        Some(placed) => {
+                            frets.push(placed);
+                            fingers.push(chord.finger(index));
+                            placed_from.push(index);
+                        }
+                        None => self.unplayable.push(Unplayable { fret: fret.clone(), pitch }),
+                    }
+                }
+                if frets.is_empty() {
+                    return None;
+                }
+                // fingerings only hold for the shape they were written for
+                let moved = frets.iter().zip(&placed_from).any(|(f, index)| f.string != chord.frets[*index].string);
+                Some(Action::Chord(Chord {
+                    frets,
+                    fingers: if moved || fingers.iter().all(Option::is_none) { vec![] } else { fingers },
+                    ..chord.clone()
+                }))
+            }
+        }
+    }
+
+    fn actions(&mut self, actions: &[Action]) -> Vec<Action> {
+        actions.iter().filter_map(|a| self.action(a)).collect()
+    }
+
+    fn data(&mut self, data: &TrackData) -> TrackData {
+        let mut retuned = data.clone();
+        retuned.actions = self.actions(&data.actions);
+        retuned.hand_positions = plan_hand(&retuned.actions);
+        for level in retuned.phrases.iter_mut().flat_map(|p| p.levels.iter_mut()) {
+            level.actions = self.actions(&level.actions);
+            level.hand_positions = plan_hand(&level.actions);
+        }
+        retuned
+    }
+}
+
+impl TrackData {
+    /// the chart written for `from` moved onto an instrument tuned to `to` with `frets` frets,
+    /// sounding the same, along with the notes that had to be dropped
+    pub fn retune(&self, from: &Tuning, to: &Tuning, frets: u8) -> (TrackData, Vec<Unplayable>) {
+        let mut refret = Refret {
+            from,
+            to,
+            semitones: 0,
+            frets: frets.saturating_sub(to.capo),
+            unplayable: vec![],
+        };
+        let data = refret.data(self);
+        (data, refret.unplayable)
+    }
+
+    /// the chart moved up by `semitones`, down when negative, on the same instrument
+    pub fn transpose(&self, tuning: &Tuning, semitones: i8, frets: u8) -> (TrackData, Vec<Unplayable>) {
+        let mut refret = Refret {
+            from: tuning,
+            to: tuning,
+            semitones: i16::from(semitones),
+            frets: frets.saturating_sub(tuning.capo),
+            unplayable: vec![],
+        };
+        let data = refret.data(self);
+        (data, refret.unplayable)
+    }
+}
+
+impl Arrangement {
+    /// the arrangement played on an instrument tuned to `tuning`, see `TrackData::retune`
+    pub fn retune(&self, tuning: &Tuning) -> (Arrangement, Vec<Unplayable>) {
+        let (data, unplayable) = self.data.retune(&self.tuning, tuning, DEFAULT_FRETS);
+        let arrangement = Arrangement {
+            tuning: tuning.clone(),
+            data,
+            ..self.clone()
+        };
+        (arrangement, unplayable)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::fret;
+    use crate::track::fret_action;
+    use crate::track::hand_position;
+    use crate::track::track_data;
+    use crate::track::Level;
+    use crate::track::Phrase;
+    use crate::track::Strum;
+    use std::time::Duration;
+
+    fn positions(data: &TrackData) -> Vec<Vec<(u8, u8)>> {
+        data.actions
+            .iter()
+            .map(|a| a.frets().iter().map(|f| (f.string, f.fret)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn test_retune() {
+        let chord = Action::Chord(Chord {
+            name: Some("E5".to_string()),
+            frets: vec![fret(1000, 1500, 0, 6), fret(1000, 1500, 2, 5), fret(1000, 1500, 2, 4)],
+            fingers: vec![None, Some(1), Some(1)],
+            strum: Some(Strum::Down),
+        });
+        let slide = Action::Slide(fret(2000, 2200, 3, 1), fret(2200, 2500, 5, 1));
+        let mut e_flat = track_data(
+            vec![fret_action(0, 500, 5, 3), fret_action(500, 1000, 0, 6), chord, slide],
+            vec![hand_position(0, 1)],
+        );
+        e_flat.phrases.push(Phrase {
+            name: "riff".to_string(),
+            starts_at: Duration::from_millis(0),
+            ends_at: Duration::from_millis(2500),
+            levels: vec![Level {
+                difficulty: 0,
+                actions: vec![fret_action(0, 500, 5, 3)],
+                hand_positions: vec![],
+            }],
+        });
+        let (standard, unplayable) = e_flat.retune(&Tuning::e_flat(), &Tuning::standard(), 24);
+        // the low open e flat has no place on a standard guitar, in the chord neither
+        assert_eq!(2, unplayable.len());
+        assert_eq!(Some(39), unplayable[0].pitch);
+        // levels get hand positions planned like the full chart
+        assert_eq!(
+            plan_hand(&standard.phrases[0].levels[0].actions),
+            standard.phrases[0].levels[0].hand_positions
+        );
+        assert!(!standard.phrases[0].levels[0].hand_positions.is_empty());
+        assert_eq!(vec![vec![(3, 4)], vec![(5, 1), (4, 1)], vec![(1, 2), (1, 4)]], positions(&standard));
+        assert_eq!(
+            vec![(1, Duration::from_millis(0))],
+            standard.hand_positions.iter().map(|p| (p.fret, p.at)).collect::<Vec<_>>()
+        );
+        match &standard.actions[1] {
+            Action::Chord(c) => assert_eq!(vec![Some(1), Some(1)], c.fingers),
+            other => panic!("unexpected {:?}", other),
+        }
+        let (back, unplayable) = standard.retune(&Tuning::standard(), &Tuning::e_flat(), 24);
+        assert!(unplayable.is_empty());
+        assert_eq!(vec![vec![(3, 5)], vec![(5, 2), (4, 2)], vec![(1, 3), (1, 5)]], positions(&back));
+    }
+
+    #[test]
+    fn test_transpose() {
+        let chart = track_data(vec![fret_action(0, 500, 0, 2), fret_action(500, 1000, 23, 1)], vec![hand_position(0, 1)]);
+        // an open string can't go lower, the note moves across
+        let (down, unplayable) = chart.transpose(&Tuning::standard(), -2, 24);
+        assert!(unplayable.is_empty());
+        assert_eq!(vec![vec![(3, 2)], vec![(1, 21)]], positions(&down));
+        let (up, unplayable) = chart.transpose(&Tuning::standard(), 2, 24);
+        assert_eq!(vec![vec![(2, 2)]], positions(&up));
+        assert_eq!(Some(89), unplayable[0].pitch);
+        let (dropped, unplayable) = Arrangement::new(chart).retune(&Tuning::drop_d());
+        assert!(unplayable.is_empty());
+        assert_eq!(Tuning::drop_d(), dropped.tuning);
+    }
+}
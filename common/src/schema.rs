@@ -0,0 +1,210 @@
+use crate::tempo::TempoMap;
+use crate::track::Arrangement;
+use crate::track::Instrument;
+use crate::track::Track;
+use crate::tuning::Tuning;
+use serde_json::Map;
+use serde_json::Value;
+use std::fmt;
+
+/// version of the track documents written now, documents without a version are 0
+pub const SCHEMA_VERSION: u64 = 2;
+
+/// upgrades of a document from the version at their index to the one after it
+const MIGRATIONS: [fn(&mut Map<String, Value>); SCHEMA_VERSION as usize] = [migrate_0_to_1, migrate_1_to_2];
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum DocumentErrorKind {
+    /// not a json object, or not a track once migrated
+    InvalidDocument(String),
+    /// written by a newer version of rustmith
+    UnknownVersion,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct DocumentError {
+    pub kind: DocumentErrorKind,
+    /// version the document was written with
+    pub version: u64,
+}
+
+impl fmt::Display for DocumentError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.kind {
+            DocumentErrorKind::InvalidDocument(reason) => {
+                write!(f, "invalid track document of version {}: {}", self.version, reason)
+            }
+            DocumentErrorKind::UnknownVersion => {
+                write!(f, "track document version {} is newer than {}", self.version, SCHEMA_VERSION)
+            }
+        }
+    }
+}
+
+fn insert_default(object: &mut Map<String, Value>, key: &str, value: Value) {
+    object.entry(key.to_string()).or_insert(value);
+}
+
+/// every fret inside an action, whatever the kind of action
+fn frets_of(value: &mut Value) -> Vec<&mut Map<String, Value>> {
+    match value {
+        Value::Object(object) => {
+            if object.contains_key("string") && object.contains_key("starts_at") {
+                vec![object]
+            } else {
+                object.values_mut().flat_map(frets_of).collect()
+            }
+        }
+        Value::Array(values) => values.iter_mut().flat_map(frets_of).collect(),
+        _ => vec![],
+    }
+}
+
+/// documents stored before versioning left out everything added after the first charts,
+/// artist, tuning, offset, tempo map, sections, phrases and techniques
+fn migrate_0_to_1(track: &mut Map<String, Value>) {
+    insert_default(track, "artist", Value::Null);
+    insert_default(track, "tuning", serde_json::to_value(Tuning::standard()).unwrap_or(Value::Null));
+    insert_default(track, "offset", Value::from(0));
+    if let Some(Value::Object(data)) = track.get_mut("data") {
+        insert_default(data, "tempo", serde_json::to_value(TempoMap::default()).unwrap_or(Value::Null));
+        insert_default(data, "sections", Value::Array(vec![]));
+        insert_default(data, "phrases", Value::Array(vec![]));
+        // frets of phrase levels are found through the phrases
+        for key in &["actions", "phrases"] {
+            if let Some(value) = data.get_mut(*key) {
+                for fret in frets_of(value) {
+                    insert_default(fret, "techniques", Value::Object(Map::new()));
+                }
+            }
+        }
+    }
+}
+
+/// tracks held a single chart with its tuning before arrangements came in
+fn migrate_1_to_2(track: &mut Map<String, Value>) {
+    let mut arrangement = Map::new();
+    arrangement.insert("name".to_string(), Value::from(Arrangement::DEFAULT_NAME));
+    arrangement.insert("instrument".to_string(), serde_json::to_value(Instrument::Guitar).unwrap_or(Value::Null));
+    for key in &["tuning", "data"] {
+        if let Some(value) = track.remove(*key) {
+            arrangement.insert(key.to_string(), value);
+        }
+    }
+    track.insert("arrangements".to_string(), Value::Array(vec![Value::Object(arrangement)]));
+}
+
+impl Track {
+    /// the json stored for this track, tagged with the current schema version
+    pub fn to_document(&self) -> serde_json::Result<String> {
+        let mut document = serde_json::to_value(self)?;
+        if let Value::Object(object) = &mut document {
+            object.insert("version".to_string(), Value::from(SCHEMA_VERSION));
+        }
+        serde_json::to_string(&document)
+    }
+
+    /// load a stored track, upgrading documents of older versions
+    pub fn from_document(json: &str) -> Result<Track, DocumentError> {
+        let invalid = |version: u64, reason: String| DocumentError {
+            kind: DocumentErrorKind::InvalidDocument(reason),
+            version,
+        };
+        let mut object = match serde_json::from_str(json) {
+            Ok(Value::Object(object)) => object,
+            Ok(_) => return Err(invalid(0, "not an object".to_string())),
+            Err(e) => return Err(invalid(0, e.to_string())),
+        };
+        let version = match object.remove("version") {
+            None => 0,
+            Some(version) => version.as_u64().ok_or_else(|| invalid(0, "version is not a number".to_string()))?,
+        };
+        if version > SCHEMA_VERSION {
+            return Err(DocumentError {
+                kind: DocumentErrorKind::UnknownVersion,
+                version,
+            });
+        }
+        for migrate in &MIGRATIONS[version as usize..] {
+            migrate(&mut object);
+        }
+        serde_json::from_value(Value::Object(object)).map_err(|e| invalid(version, e.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::fret_action;
+    use crate::track::hand_position;
+    use crate::track::Action;
+    use crate::track::Techniques;
+
+    #[test]
+    fn test_baseline_document() {
+        let track = Track::from_document(include_str!("../fixtures/track-v0-baseline.json")).unwrap();
+        assert_eq!("Riff", track.name);
+        assert_eq!(None, track.artist);
+        assert_eq!(Tuning::standard(), track.arrangements[0].tuning);
+        assert_eq!(0, track.offset);
+        assert_eq!(
+            vec![fret_action(0, 500, 3, 6), fret_action(500, 1000, 5, 5)],
+            track.arrangements[0].data.actions[..2].to_vec()
+        );
+        match &track.arrangements[0].data.actions[2] {
+            Action::Bend(f, _) => assert_eq!(Techniques::default(), f.techniques),
+            other => panic!("unexpected {:?}", other),
+        }
+        assert_eq!(vec![hand_position(0, 3)], track.arrangements[0].data.hand_positions);
+        assert_eq!(TempoMap::default(), track.arrangements[0].data.tempo);
+    }
+
+    #[test]
+    fn test_unversioned_document() {
+        // stored after tunings, tempo maps and phrases came in but before documents had a version
+        let track = Track::from_document(include_str!("../fixtures/track-v0.json")).unwrap();
+        assert_eq!(Tuning::drop_d(), track.arrangements[0].tuning);
+        assert_eq!(Some("Band".to_string()), track.artist);
+        assert_eq!(90.0, track.arrangements[0].data.tempo.tempos[0].bpm);
+        assert_eq!(
+            Techniques::default(),
+            track.arrangements[0].data.phrases[0].levels[0].actions[0].frets()[0].techniques
+        );
+    }
+
+    #[test]
+    fn test_single_chart_document() {
+        let track = Track::from_document(include_str!("../fixtures/track-v1.json")).unwrap();
+        assert_eq!(1, track.arrangements.len());
+        let lead = &track.arrangements[0];
+        assert_eq!((Arrangement::DEFAULT_NAME, Instrument::Guitar), (lead.name.as_str(), lead.instrument));
+        assert_eq!(Tuning::e_flat().with_capo(2), lead.tuning);
+        assert!(lead.data.actions[0].frets()[0].techniques.hammer_on);
+        assert_eq!(-120, track.offset);
+    }
+
+    #[test]
+    fn test_current_document() {
+        let track = Track::from_document(include_str!("../fixtures/track-v2.json")).unwrap();
+        let names: Vec<&str> = track.arrangements.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(vec!["Lead", "Bass"], names);
+        assert_eq!(
+            (Instrument::Bass, Tuning::bass()),
+            (track.arrangements[1].instrument, track.arrangements[1].tuning.clone())
+        );
+        let document = track.to_document().unwrap();
+        assert!(document.contains("\"version\":2"));
+        assert_eq!(track, Track::from_document(&document).unwrap());
+    }
+
+    #[test]
+    fn test_document_errors() {
+        let error = Track::from_document("{\"version\":7}").unwrap_err();
+        assert_eq!(DocumentErrorKind::UnknownVersion, error.kind);
+        assert_eq!("track document version 7 is newer than 2", error.to_string());
+        let error = Track::from_document("[]").unwrap_err();
+        assert_eq!(DocumentErrorKind::InvalidDocument("not an object".to_string()), error.kind);
+        let error = Track::from_document("{\"version\":1,\"name\":\"x\"}").unwrap_err();
+        assert_eq!(1, error.version);
+    }
+}
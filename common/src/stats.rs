@@ -0,0 +1,231 @@
+use crate::ext::DurationExt;
+use crate::track::Action;
+use crate::track::Track;
+use crate::track::TrackData;
+use std::cmp::Ordering;
+use std::time::Duration;
+
+/// notes starting closer together than this make up a dense passage
+const DENSE_GAP: Duration = Duration::from_millis(250);
+/// weights of each measure in the difficulty before it is scaled to 0..100, per note per second
+const DENSITY_WEIGHT: f64 = 2.0;
+/// per note in the busiest second
+const PEAK_WEIGHT: f64 = 1.0;
+/// per shift per minute
+const SHIFT_WEIGHT: f64 = 0.1;
+/// per share of notes played with a technique
+const TECHNIQUE_WEIGHT: f64 = 5.0;
+/// per fret between the lowest and highest fretted note
+const SPREAD_WEIGHT: f64 = 0.25;
+/// per second of the longest dense passage
+const DENSE_WEIGHT: f64 = 0.2;
+
+/// how often each technique is played
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct TechniqueCounts {
+    pub hammer_ons: usize,
+    pub pull_offs: usize,
+    pub palm_mutes: usize,
+    pub harmonics: usize,
+    pub vibratos: usize,
+    pub taps: usize,
+    pub tremolos: usize,
+    pub dead_notes: usize,
+    pub slides: usize,
+    pub bends: usize,
+    pub chords: usize,
+}
+
+impl TechniqueCounts {
+    pub fn total(&self) -> usize {
+        self.hammer_ons
+            + self.pull_offs
+            + self.palm_mutes
+            + self.harmonics
+            + self.vibratos
+            + self.taps
+            + self.tremolos
+            + self.dead_notes
+            + self.slides
+            + self.bends
+            + self.chords
+    }
+}
+
+/// what it takes to play the full chart of a track, every action counts as one note
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct TrackStats {
+    pub notes: usize,
+    /// notes per second from the start of the first note to the end of the last
+    pub average_density: f64,
+    /// most notes starting within any one second
+    pub peak_density: usize,
+    pub shifts: usize,
+    /// frets travelled by all hand shifts together
+    pub shift_distance: u32,
+    pub techniques: TechniqueCounts,
+    /// lowest and highest fretted note, `None` when only open strings are played
+    pub fret_range: Option<(u8, u8)>,
+    /// the longest run of notes following each other closer than `DENSE_GAP`
+    pub longest_dense_passage: Duration,
+    /// 0 for an empty chart, getting closer to 100 the harder it is
+    pub difficulty: f64,
+}
+
+fn count_techniques(actions: &[&Action]) -> TechniqueCounts {
+    let mut counts = TechniqueCounts::default();
+    for action in actions {
+        match action {
+            Action::Slide(_, _) => counts.slides += 1,
+            Action::Bend(_, _) => counts.bends += 1,
+            Action::Chord(_) => counts.chords += 1,
+            Action::Fret(_) => (),
+        }
+        for fret in action.frets() {
+            let t = &fret.techniques;
+            counts.hammer_ons += t.hammer_on as usize;
+            counts.pull_offs += t.pull_off as usize;
+            counts.palm_mutes += t.palm_mute as usize;
+            counts.harmonics += t.harmonic.is_some() as usize;
+            counts.vibratos += t.vibrato as usize;
+            counts.taps += t.tap as usize;
+            counts.tremolos += t.tremolo as usize;
+            counts.dead_notes += t.dead as usize;
+        }
+    }
+    counts
+}
+
+fn longest_dense_passage(actions: &[&Action]) -> Duration {
+    let mut longest = Duration::from_millis(0);
+    let mut start = 0;
+    for index in 1..=actions.len() {
+        let ends = index == actions.len() || *actions[index].starts_at() - *actions[index - 1].starts_at() >= DENSE_GAP;
+        if ends {
+            if index - start > 1 {
+                longest = longest.max(*actions[index - 1].ends_at() - *actions[start].starts_at());
+            }
+            start = index;
+        }
+    }
+    longest
+}
+
+impl TrackData {
+    pub fn stats(&self) -> TrackStats {
+        let mut actions: Vec<&Action> = self.actions.iter().filter(|a| a.ends_at() > a.starts_at()).collect();
+        actions.sort_by_key(|a| *a.starts_at());
+        let (first, last) = match (actions.first(), actions.iter().map(|a| *a.ends_at()).max()) {
+            (Some(first), Some(last)) => (*first.starts_at(), last),
+            _ => return TrackStats::default(),
+        };
+        let seconds = (last - first).total_millis().max(1) as f64 / 1000.0;
+        let notes = actions.len();
+
+        let mut peak_density = 0;
+        let mut window_start = 0;
+        for (index, action) in actions.iter().enumerate() {
+            while *action.starts_at() - *actions[window_start].starts_at() >= Duration::from_secs(1) {
+                window_start += 1;
+            }
+            peak_density = peak_density.max(index + 1 - window_start);
+        }
+
+        let mut hand_positions: Vec<u8> = vec![];
+        let mut sorted = self.hand_positions.clone();
+        sorted.sort_by_key(|p| p.at);
+        for position in sorted {
+            if hand_positions.last() != Some(&position.fret) {
+                hand_positions.push(position.fret);
+            }
+        }
+        let shifts = hand_positions.len().saturating_sub(1);
+        let shift_distance = hand_positions.windows(2).map(|w| u32::from(w[0].max(w[1]) - w[0].min(w[1]))).sum();
+
+        let fretted = actions.iter().flat_map(|a| a.frets()).map(|f| f.fret).filter(|f| *f > 0);
+        let fret_range = match (fretted.clone().min(), fretted.max()) {
+            (Some(low), Some(high)) => Some((low, high)),
+            _ => None,
+        };
+        let techniques = count_techniques(&actions);
+        let longest_dense_passage = longest_dense_passage(&actions);
+
+        let average_density = notes as f64 / seconds;
+        let raw = average_density * DENSITY_WEIGHT
+            + peak_density as f64 * PEAK_WEIGHT
+            + shifts as f64 * 60.0 / seconds * SHIFT_WEIGHT
+            + techniques.total() as f64 / notes as f64 * TECHNIQUE_WEIGHT
+            + fret_range.map(|(low, high)| f64::from(high - low)).unwrap_or(0.0) * SPREAD_WEIGHT
+            + longest_dense_passage.total_millis() as f64 / 1000.0 * DENSE_WEIGHT;
+        TrackStats {
+            notes,
+            average_density,
+            peak_density,
+            shifts,
+            shift_distance,
+            techniques,
+            fret_range,
+            longest_dense_passage,
+            difficulty: 100.0 * (1.0 - (-raw / 20.0).exp()),
+        }
+    }
+}
+
+impl Track {
+    /// stats of the hardest arrangement
+    pub fn stats(&self) -> TrackStats {
+        self.arrangements
+            .iter()
+            .map(|a| a.data.stats())
+            .max_by(|a, b| a.difficulty.partial_cmp(&b.difficulty).unwrap_or(Ordering::Equal))
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::fret;
+    use crate::track::fret_action;
+    use crate::track::hand_position;
+    use crate::track::track_data;
+    use crate::track::Fret;
+    use crate::track::Techniques;
+
+    #[test]
+    fn test_stats() {
+        let hammered = Fret {
+            techniques: Techniques {
+                hammer_on: true,
+                ..Techniques::default()
+            },
+            ..fret(1600, 1800, 7, 3)
+        };
+        let track = track_data(
+            vec![
+                fret_action(0, 1000, 0, 6),
+                fret_action(1000, 1200, 5, 3),
+                fret_action(1200, 1400, 5, 3),
+                fret_action(1400, 1600, 5, 3),
+                Action::Fret(hammered),
+                Action::Slide(fret(3000, 3500, 12, 2), fret(3500, 4000, 14, 2)),
+            ],
+            vec![hand_position(0, 5), hand_position(3000, 12), hand_position(3100, 12)],
+        );
+        let stats = track.stats();
+        assert_eq!(6, stats.notes);
+        assert_eq!(1.5, stats.average_density);
+        assert_eq!(4, stats.peak_density);
+        assert_eq!((1, 7), (stats.shifts, stats.shift_distance));
+        assert_eq!((1, 1), (stats.techniques.hammer_ons, stats.techniques.slides));
+        assert_eq!(Some((5, 14)), stats.fret_range);
+        assert_eq!(Duration::from_millis(800), stats.longest_dense_passage);
+        assert!(stats.difficulty > 0.0 && stats.difficulty < 100.0);
+
+        // faster is harder
+        let mut faster = track.clone();
+        faster.stretch(0.5);
+        assert!(faster.stats().difficulty > stats.difficulty);
+        assert_eq!(TrackStats::default(), track_data(vec![], vec![]).stats());
+    }
+}
@@ -0,0 +1,289 @@
+use crate::ext::DurationExt;
+use std::time::Duration;
+
+/// charts without a tempo map play at 120 beats per minute in 4/4
+const DEFAULT_BPM: f64 = 120.0;
+const DEFAULT_SIGNATURE: TimeSignature = TimeSignature {
+    measure: 0,
+    numerator: 4,
+    denominator: 4,
+};
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct TempoChange {
+    pub at: Duration,
+    /// quarter notes per minute
+    pub bpm: f64,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct TimeSignature {
+    /// first measure in this signature, counting from 0
+    pub measure: u32,
+    pub numerator: u8,
+    pub denominator: u8,
+}
+
+impl TimeSignature {
+    /// length of one beat in quarter notes
+    fn beat_length(&self) -> f64 {
+        4.0 / f64::from(self.denominator.max(1))
+    }
+
+    fn measure_length(&self) -> f64 {
+        f64::from(self.numerator.max(1)) * self.beat_length()
+    }
+}
+
+/// tempo changes and time signatures of a chart, the first tempo change starts
+/// the first measure, a beat lasts as long as the time signature's note value
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct TempoMap {
+    #[serde(default)]
+    pub tempos: Vec<TempoChange>,
+    #[serde(default)]
+    pub time_signatures: Vec<TimeSignature>,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Beat {
+    pub at: Duration,
+    pub measure: u32,
+    /// position in the measure, 0 being the downbeat
+    pub beat: u32,
+}
+
+/// a time signature together with where it starts in quarter notes and in beats
+struct SignatureSpan {
+    signature: TimeSignature,
+    quarter: f64,
+    beat: f64,
+}
+
+impl TempoMap {
+    /// build a tempo map from tempo changes at positions given in quarter notes
+    pub fn from_quarters(mut tempos: Vec<(f64, f64)>, time_signatures: Vec<TimeSignature>) -> TempoMap {
+        tempos.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(::std::cmp::Ordering::Equal));
+        tempos.retain(|(_, bpm)| *bpm > 0.0);
+        let mut map = TempoMap {
+            tempos: vec![],
+            time_signatures,
+        };
+        for (quarter, bpm) in tempos {
+            let at = map.time_of_quarter(quarter);
+            match map.tempos.last_mut() {
+                Some(last) if last.at == at => last.bpm = bpm,
+                Some(last) if last.bpm == bpm => (),
+                _ => map.tempos.push(TempoChange { at, bpm }),
+            }
+        }
+        map
+    }
+
+    /// tempo changes in order, charts without any play at the default tempo
+    pub fn changes(&self) -> Vec<TempoChange> {
+        let mut tempos: Vec<TempoChange> = self.tempos.iter().filter(|t| t.bpm > 0.0).cloned().collect();
+        tempos.sort_by_key(|t| t.at);
+        if tempos.is_empty() {
+            tempos.push(TempoChange {
+                at: Duration::from_millis(0),
+                bpm: DEFAULT_BPM,
+            });
+        }
+        tempos
+    }
+
+    /// start in milliseconds, start in quarter notes and tempo of every tempo change
+    fn tempo_spans(&self) -> Vec<(f64, f64, f64)> {
+        let mut spans: Vec<(f64, f64, f64)> = vec![];
+        for tempo in self.changes() {
+            let millis = tempo.at.total_millis() as f64;
+            let quarter = spans
+                .last()
+                .map(|(from, quarter, bpm)| quarter + (millis - from) * bpm / 60_000.0)
+                .unwrap_or(0.0);
+            spans.push((millis, quarter, tempo.bpm));
+        }
+        spans
+    }
+
+    fn signature_spans(&self) -> Vec<SignatureSpan> {
+        let mut signatures: Vec<&TimeSignature> = self.time_signatures.iter().collect();
+        signatures.sort_by_key(|s| s.measure);
+        if signatures.first().map(|s| s.measure > 0).unwrap_or(true) {
+            signatures.insert(0, &DEFAULT_SIGNATURE);
+        }
+        let mut spans: Vec<SignatureSpan> = vec![];
+        for signature in signatures {
+            let (quarter, beat) = match spans.last() {
+                Some(last) => {
+                    let measures = f64::from(signature.measure - last.signature.measure);
+                    (
+                        last.quarter + measures * last.signature.measure_length(),
+                        last.beat + measures * f64::from(last.signature.numerator.max(1)),
+                    )
+                }
+                None => (0.0, 0.0),
+            };
+            spans.push(SignatureSpan {
+                signature: signature.clone(),
+                quarter,
+                beat,
+            });
+        }
+        spans
+    }
+
+    /// quarter notes elapsed since the first tempo change
+    pub fn quarter_at(&self, at: Duration) -> f64 {
+        let millis = at.total_millis() as f64;
+        let spans = self.tempo_spans();
+        let index = spans.iter().rposition(|(from, _, _)| *from <= millis).unwrap_or(0);
+        let (from, quarter, bpm) = spans[index];
+        quarter + (millis - from) * bpm / 60_000.0
+    }
+
+    pub fn time_of_quarter(&self, quarter: f64) -> Duration {
+        let spans = self.tempo_spans();
+        let index = spans.iter().rposition(|(_, from, _)| *from <= quarter).unwrap_or(0);
+        let (millis, from, bpm) = spans[index];
+        Duration::from_millis((millis + (quarter - from) * 60_000.0 / bpm).max(0.0).round() as u64)
+    }
+
+    /// beats elapsed since the first measure started, fractional between beats
+    pub fn beat_at(&self, at: Duration) -> f64 {
+        let quarter = self.quarter_at(at).max(0.0);
+        let spans = self.signature_spans();
+        let span = spans.iter().rev().find(|s| s.quarter <= quarter).unwrap_or(&spans[0]);
+        span.beat + (quarter - span.quarter) / span.signature.beat_length()
+    }
+
+    pub fn time_of_beat(&self, beat: f64) -> Duration {
+        let spans = self.signature_spans();
+        let span = spans.iter().rev().find(|s| s.beat <= beat).unwrap_or(&spans[0]);
+        self.time_of_quarter(span.quarter + (beat - span.beat) * span.signature.beat_length())
+    }
+
+    /// measure playing at `at` and the fractional beat within it
+    pub fn measure_at(&self, at: Duration) -> (u32, f64) {
+        self.measure_of_beat(self.beat_at(at))
+    }
+
+    fn measure_of_beat(&self, beat: f64) -> (u32, f64) {
+        let spans = self.signature_spans();
+        let span = spans.iter().rev().find(|s| s.beat <= beat).unwrap_or(&spans[0]);
+        let numerator = f64::from(span.signature.numerator.max(1));
+        let offset = (beat - span.beat).max(0.0);
+        let measures = (offset / numerator).floor();
+        (span.signature.measure + measures as u32, offset - measures * numerator)
+    }
+
+    pub fn time_of_measure(&self, measure: u32) -> Duration {
+        let spans = self.signature_spans();
+        let span = spans.iter().rev().find(|s| s.signature.measure <= measure).unwrap_or(&spans[0]);
+        let measures = f64::from(measure - span.signature.measure);
+        self.time_of_quarter(span.quarter + measures * span.signature.measure_length())
+    }
+
+    pub fn time_signature_at(&self, measure: u32) -> TimeSignature {
+        let spans = self.signature_spans();
+        let span = spans.iter().rev().find(|s| s.signature.measure <= measure).unwrap_or(&spans[0]);
+        span.signature.clone()
+    }
+
+    /// every beat between `from` and `to`, both included
+    pub fn beats(&self, from: Duration, to: Duration) -> Vec<Beat> {
+        let mut beats = vec![];
+        let mut beat = self.beat_at(from).ceil();
+        loop {
+            let at = self.time_of_beat(beat);
+            if at > to {
+                return beats;
+            }
+            let (measure, in_measure) = self.measure_of_beat(beat);
+            beats.push(Beat {
+                at,
+                measure,
+                beat: in_measure.round() as u32,
+            });
+            beat += 1.0;
+        }
+    }
+
+    /// the closest point of a grid dividing every beat into `subdivision` parts
+    pub fn snap(&self, at: Duration, subdivision: u32) -> Duration {
+        let subdivision = f64::from(subdivision.max(1));
+        self.time_of_beat((self.beat_at(at) * subdivision).round() / subdivision)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn tempo_map() -> TempoMap {
+        TempoMap {
+            tempos: vec![
+                TempoChange {
+                    at: millis(1000),
+                    bpm: 120.0,
+                },
+                TempoChange { at: millis(5000), bpm: 60.0 },
+            ],
+            time_signatures: vec![TimeSignature {
+                measure: 2,
+                numerator: 6,
+                denominator: 8,
+            }],
+        }
+    }
+
+    #[test]
+    fn test_default_tempo_map() {
+        let map = TempoMap::default();
+        assert_eq!(4.0, map.beat_at(millis(2000)));
+        assert_eq!(millis(2250), map.time_of_beat(4.5));
+        assert_eq!((1, 0.5), map.measure_at(millis(2250)));
+        assert_eq!(millis(4000), map.time_of_measure(2));
+    }
+
+    #[test]
+    fn test_tempo_changes_and_signatures() {
+        let map = tempo_map();
+        // two 4/4 measures at 120 take 4 seconds, then 6/8 where a beat is an eighth note
+        assert_eq!(millis(5000), map.time_of_measure(2));
+        assert_eq!(8.0, map.beat_at(millis(5000)));
+        assert_eq!(millis(5500), map.time_of_beat(9.0));
+        assert_eq!((2, 1.0), map.measure_at(millis(5500)));
+        assert_eq!(millis(8000), map.time_of_measure(3));
+        assert_eq!(6, map.time_signature_at(5).numerator);
+        assert_eq!(millis(1000), map.time_of_quarter(0.0));
+    }
+
+    #[test]
+    fn test_beats_and_snap() {
+        let map = tempo_map();
+        let beats: Vec<(u64, u32, u32)> = map
+            .beats(millis(4000), millis(6000))
+            .iter()
+            .map(|b| (b.at.total_millis(), b.measure, b.beat))
+            .collect();
+        assert_eq!(vec![(4000, 1, 2), (4500, 1, 3), (5000, 2, 0), (5500, 2, 1), (6000, 2, 2)], beats);
+        assert_eq!(millis(4500), map.snap(millis(4600), 1));
+        assert_eq!(millis(4750), map.snap(millis(4700), 2));
+    }
+
+    #[test]
+    fn test_from_quarters() {
+        let map = TempoMap::from_quarters(vec![(8.0, 60.0), (0.0, 120.0), (4.0, 120.0)], vec![]);
+        assert_eq!(
+            vec![TempoChange { at: millis(0), bpm: 120.0 }, TempoChange { at: millis(4000), bpm: 60.0 }],
+            map.tempos
+        );
+        assert_eq!(millis(5000), map.time_of_quarter(9.0));
+    }
+}
@@ -1,12 +1,60 @@
-use std::num::ParseIntError;
+use crate::format::text::parse_track;
+use crate::format::text::parse_track_data;
+use crate::format::text::TrackParseError;
+use crate::stats::TrackStats;
+use crate::tempo::TempoMap;
+use crate::tuning::Tuning;
+use crate::validate::validate;
+use crate::validate::Diagnostic;
+use crate::validate::DEFAULT_FRETS;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::time::Duration;
 
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub enum Harmonic {
+    Natural,
+    Pinch,
+}
+
+/// how a single note is played besides fretting and picking it
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
+pub struct Techniques {
+    /// sounded by hammering onto the string from the note before
+    #[serde(default)]
+    pub hammer_on: bool,
+    /// sounded by pulling off from the note before
+    #[serde(default)]
+    pub pull_off: bool,
+    #[serde(default)]
+    pub palm_mute: bool,
+    #[serde(default)]
+    pub harmonic: Option<Harmonic>,
+    #[serde(default)]
+    pub vibrato: bool,
+    #[serde(default)]
+    pub tap: bool,
+    #[serde(default)]
+    pub tremolo: bool,
+    /// muted by the fretting hand, percussive without a pitch
+    #[serde(default)]
+    pub dead: bool,
+}
+
+impl Techniques {
+    pub fn is_plain(&self) -> bool {
+        *self == Techniques::default()
+    }
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
 pub struct Fret {
     pub fret: u8,
     pub string: u8,
     pub ends_at: Duration,
     pub starts_at: Duration,
+    #[serde(default)]
+    pub techniques: Techniques,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
@@ -16,11 +64,51 @@ pub enum Interval {
     DoubleStep,
 }
 
+impl Interval {
+    pub fn semitones(&self) -> u8 {
+        match self {
+            Interval::HalfStep => 1,
+            Interval::Step => 2,
+            Interval::DoubleStep => 4,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub enum Strum {
+    Down,
+    Up,
+}
+
+/// strings played together, like an `Am7`
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct Chord {
+    #[serde(default)]
+    pub name: Option<String>,
+    pub frets: Vec<Fret>,
+    /// finger holding each of `frets`, 1 being the index finger and 0 the thumb,
+    /// `None` for open strings, empty when the fingering is unknown
+    #[serde(default)]
+    pub fingers: Vec<Option<u8>>,
+    #[serde(default)]
+    pub strum: Option<Strum>,
+}
+
+impl Chord {
+    pub fn finger(&self, index: usize) -> Option<u8> {
+        self.fingers.get(index).cloned().unwrap_or(None)
+    }
+}
+
+/// start of a chord without frets
+static NO_TIME: Duration = Duration::from_millis(0);
+
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
 pub enum Action {
     Fret(Fret),
     Slide(Fret, Fret),
     Bend(Fret, Interval),
+    Chord(Chord),
 }
 
 impl Action {
@@ -29,6 +117,7 @@ impl Action {
             Action::Fret(f) => &f.starts_at,
             Action::Slide(f1, f2) => ::std::cmp::min(&f1.starts_at, &f2.starts_at),
             Action::Bend(f, _) => &f.starts_at,
+            Action::Chord(c) => c.frets.iter().map(|f| &f.starts_at).min().unwrap_or(&NO_TIME),
         }
     }
 
@@ -37,6 +126,25 @@ impl Action {
             Action::Fret(f) => &f.ends_at,
             Action::Slide(f1, f2) => ::std::cmp::max(&f1.ends_at, &f2.ends_at),
             Action::Bend(f, _) => &f.ends_at,
+            Action::Chord(c) => c.frets.iter().map(|f| &f.ends_at).max().unwrap_or(&NO_TIME),
+        }
+    }
+
+    pub fn frets(&self) -> Vec<&Fret> {
+        match self {
+            Action::Fret(f) => vec![f],
+            Action::Slide(f1, f2) => vec![f1, f2],
+            Action::Bend(f, _) => vec![f],
+            Action::Chord(c) => c.frets.iter().collect(),
+        }
+    }
+
+    pub fn frets_mut(&mut self) -> Vec<&mut Fret> {
+        match self {
+            Action::Fret(f) => vec![f],
+            Action::Slide(f1, f2) => vec![f1, f2],
+            Action::Bend(f, _) => vec![f],
+            Action::Chord(c) => c.frets.iter_mut().collect(),
         }
     }
 }
@@ -47,61 +155,364 @@ pub struct HandPosition {
     pub at: Duration,
 }
 
+/// a named part of the song like a verse or a solo, lasting until the next section starts
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct Section {
+    pub name: String,
+    pub starts_at: Duration,
+}
+
+/// actions of a phrase reduced to a lower difficulty, 0 being the easiest
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct Level {
+    pub difficulty: u8,
+    pub actions: Vec<Action>,
+    #[serde(default)]
+    pub hand_positions: Vec<HandPosition>,
+}
+
+/// a stretch of the chart that can be played at several difficulties,
+/// the actions of the track are its hardest level
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct Phrase {
+    pub name: String,
+    pub starts_at: Duration,
+    pub ends_at: Duration,
+    #[serde(default)]
+    pub levels: Vec<Level>,
+}
+
+impl Phrase {
+    pub fn contains(&self, at: Duration) -> bool {
+        self.starts_at <= at && at < self.ends_at
+    }
+
+    /// difficulty of the full chart, one above the hardest reduced level
+    pub fn max_difficulty(&self) -> u8 {
+        self.levels.iter().map(|l| l.difficulty.saturating_add(1)).max().unwrap_or(0)
+    }
+
+    /// the hardest level not above `difficulty`, falling back to the easiest one,
+    /// `None` when the full chart should be played
+    pub fn level(&self, difficulty: u8) -> Option<&Level> {
+        if difficulty >= self.max_difficulty() {
+            return None;
+        }
+        self.levels
+            .iter()
+            .filter(|l| l.difficulty <= difficulty)
+            .max_by_key(|l| l.difficulty)
+            .or_else(|| self.levels.iter().min_by_key(|l| l.difficulty))
+    }
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug, Default)]
 pub struct TrackData {
     pub actions: Vec<Action>,
     pub hand_positions: Vec<HandPosition>,
+    #[serde(default)]
+    pub tempo: TempoMap,
+    #[serde(default)]
+    pub sections: Vec<Section>,
+    #[serde(default)]
+    pub phrases: Vec<Phrase>,
+    #[serde(default)]
+    pub lyrics: Vec<Lyric>,
+}
+
+/// a syllable, word or whole line of the lyrics sung from `at`,
+/// a syllable ending in `-` is joined to the next one without a space
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct Lyric {
+    pub at: Duration,
+    pub text: String,
+    /// the last lyric of its line
+    #[serde(default)]
+    pub line_end: bool,
+}
+
+/// lyrics joined into the line they are shown on
+#[derive(PartialEq, Clone, Debug)]
+pub struct LyricLine {
+    pub starts_at: Duration,
+    pub text: String,
+}
+
+impl TrackData {
+    /// the lyrics as lines sorted by time, a line also ends where the next lyric starts one
+    pub fn lyric_lines(&self) -> Vec<LyricLine> {
+        let mut lyrics: Vec<&Lyric> = self.lyrics.iter().collect();
+        lyrics.sort_by_key(|l| l.at);
+        let mut lines: Vec<LyricLine> = vec![];
+        let mut open = false;
+        for lyric in lyrics {
+            match lines.last_mut() {
+                Some(line) if open => {
+                    if line.text.ends_with('-') {
+                        line.text.pop();
+                    } else {
+                        line.text.push(' ');
+                    }
+                    line.text.push_str(&lyric.text);
+                }
+                _ => lines.push(LyricLine {
+                    starts_at: lyric.at,
+                    text: lyric.text.clone(),
+                }),
+            }
+            open = !lyric.line_end;
+        }
+        for line in &mut lines {
+            if line.text.ends_with('-') {
+                line.text.pop();
+            }
+        }
+        lines
+    }
+}
+
+/// what an arrangement is played on, which picks its default tuning
+#[derive(Serialize, Deserialize, PartialEq, Clone, Copy, Debug, Default)]
+pub enum Instrument {
+    #[default]
+    Guitar,
+    Bass,
+}
+
+impl Instrument {
+    pub fn standard_tuning(self) -> Tuning {
+        match self {
+            Instrument::Guitar => Tuning::standard(),
+            Instrument::Bass => Tuning::bass(),
+        }
+    }
+}
+
+/// one way of playing a song, like its lead, rhythm or bass part
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct Arrangement {
+    pub name: String,
+    #[serde(default)]
+    pub instrument: Instrument,
+    #[serde(default)]
+    pub tuning: Tuning,
+    pub data: TrackData,
+}
+
+impl Arrangement {
+    /// name of the arrangement of tracks that only have one
+    pub const DEFAULT_NAME: &'static str = "Lead";
+
+    pub fn new(data: TrackData) -> Arrangement {
+        Arrangement {
+            name: Arrangement::DEFAULT_NAME.to_string(),
+            instrument: Instrument::Guitar,
+            tuning: Tuning::standard(),
+            data,
+        }
+    }
+
+    /// check the chart against the tuning of the arrangement on a neck of `DEFAULT_FRETS` frets
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        self.data.validate(&self.tuning, DEFAULT_FRETS)
+    }
+
+    pub fn summary(&self) -> ArrangementSummary {
+        ArrangementSummary {
+            name: self.name.clone(),
+            instrument: self.instrument,
+            tuning: self.tuning.clone(),
+        }
+    }
+}
+
+/// an arrangement without its chart, enough to pick one before playing
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct ArrangementSummary {
+    pub name: String,
+    pub instrument: Instrument,
+    pub tuning: Tuning,
 }
 
 #[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
 pub struct Track {
     pub id: String,
     pub name: String,
+    #[serde(default)]
+    pub artist: Option<String>,
     pub youtube_id: String,
-    pub data: TrackData,
+    /// milliseconds to add to chart times to line them up with the video
+    #[serde(default)]
+    pub offset: i64,
+    pub arrangements: Vec<Arrangement>,
+}
+
+impl Track {
+    pub fn parse(content: &str) -> Result<Track, Vec<TrackParseError>> {
+        parse_track(content)
+    }
+
+    /// check every arrangement, diagnostics come with the index of their arrangement
+    pub fn validate(&self) -> Vec<(usize, Diagnostic)> {
+        self.arrangements
+            .iter()
+            .enumerate()
+            .flat_map(|(index, a)| a.validate().into_iter().map(move |d| (index, d)))
+            .collect()
+    }
+
+    pub fn summaries(&self) -> Vec<ArrangementSummary> {
+        self.arrangements.iter().map(Arrangement::summary).collect()
+    }
 }
 
+/// actions sounding inside a window of the chart and the hand positions from the one
+/// in effect at its start, both sorted by time
 pub struct TrackView<'a> {
     pub actions: Vec<&'a Action>,
     pub hand_positions: Vec<&'a HandPosition>,
+    /// lyric lines from the one in effect at the start of the window
+    pub lyrics: Vec<&'a LyricLine>,
 }
 
-impl TrackData {
-    pub fn parse(content: &str) -> Result<TrackData, ParseIntError> {
-        let mut actions: Vec<Action> = vec![];
-        let mut hand_positions: Vec<HandPosition> = vec![];
-        let lines: Vec<&str> = content.split('\n').collect();
-        for line in lines {
-            let segments: Vec<&str> = line.split(':').collect();
-            match segments.first() {
-                Some(&"fret") => actions.push(fret_action(
-                    segments[1].parse::<u64>()?,
-                    segments[2].parse::<u64>()?,
-                    segments[3].parse::<u8>()?,
-                    segments[4].parse::<u8>()?,
-                )),
-                Some(&"hand") => hand_positions.push(hand_position(segments[1].parse::<u64>()?, segments[2].parse::<u8>()?)),
-                _ => (),
-            }
+/// how much of the chart around the current time a view covers
+#[derive(PartialEq, Clone, Debug)]
+pub struct Window {
+    pub behind: Duration,
+    pub ahead: Duration,
+}
+
+impl Default for Window {
+    fn default() -> Self {
+        Window {
+            behind: Duration::from_millis(0),
+            ahead: Duration::from_secs(60),
         }
-        Result::Ok(TrackData { actions, hand_positions })
     }
+}
+
+/// the chart played at one difficulty selection, sorted by time so views are
+/// found by binary search instead of going over the whole chart
+pub struct TrackIndex {
+    actions: Vec<Action>,
+    hand_positions: Vec<HandPosition>,
+    lyrics: Vec<LyricLine>,
+    /// no action lasts longer, so nothing starting earlier than this before a window reaches into it
+    longest: Duration,
+}
 
-    pub fn view(&self, from: Duration) -> TrackView {
-        let until = from + Duration::from_secs(60);
-        let actions = self.actions.iter().filter(|a| from <= *a.starts_at() && *a.ends_at() <= until).collect();
-        let hand_positions = self.hand_positions.iter().filter(|p| from <= p.at).collect();
-        TrackView { actions, hand_positions }
+impl TrackIndex {
+    pub fn new(data: &TrackData, difficulty: &DifficultySelection) -> TrackIndex {
+        let reduced: Vec<(&Phrase, &Level)> = data
+            .phrases
+            .iter()
+            .enumerate()
+            .filter_map(|(index, p)| difficulty.of(index).and_then(|d| p.level(d)).map(|l| (p, l)))
+            .collect();
+        let full = |at: Duration| !reduced.iter().any(|(p, _)| p.contains(at));
+        let mut actions: Vec<Action> = data.actions.iter().filter(|a| full(*a.starts_at())).cloned().collect();
+        let mut hand_positions: Vec<HandPosition> = data.hand_positions.iter().filter(|p| full(p.at)).cloned().collect();
+        for (phrase, level) in &reduced {
+            actions.extend(level.actions.iter().filter(|a| phrase.contains(*a.starts_at())).cloned());
+            hand_positions.extend(level.hand_positions.iter().filter(|p| phrase.contains(p.at)).cloned());
+        }
+        actions.sort_by_key(|a| *a.starts_at());
+        hand_positions.sort_by_key(|p| p.at);
+        let longest = actions
+            .iter()
+            .map(|a| a.ends_at().checked_sub(*a.starts_at()).unwrap_or_default())
+            .max()
+            .unwrap_or_default();
+        TrackIndex {
+            actions,
+            hand_positions,
+            lyrics: data.lyric_lines(),
+            longest,
+        }
+    }
+
+    /// actions overlapping the window around `at`, including ones still ringing from before it
+    pub fn view(&self, at: Duration, window: &Window) -> TrackView<'_> {
+        let from = at.checked_sub(window.behind).unwrap_or_default();
+        let until = at + window.ahead;
+        let earliest = from.checked_sub(self.longest).unwrap_or_default();
+        let first = first_not(&self.actions, |a| *a.starts_at() < earliest);
+        let actions = self.actions[first..]
+            .iter()
+            .take_while(|a| *a.starts_at() < until)
+            .filter(|a| *a.ends_at() > from)
+            .collect();
+        let current = first_not(&self.hand_positions, |p| p.at <= from).saturating_sub(1);
+        let hand_positions = self.hand_positions[current..].iter().take_while(|p| p.at < until).collect();
+        let current = first_not(&self.lyrics, |l| l.starts_at <= from).saturating_sub(1);
+        let lyrics = self.lyrics[current..].iter().take_while(|l| l.starts_at < until).collect();
+        TrackView {
+            actions,
+            hand_positions,
+            lyrics,
+        }
     }
 }
 
-pub fn fret_action(starts_at: u64, ends_at: u64, fret: u8, string: u8) -> Action {
-    Action::Fret(Fret {
+/// difficulty every phrase is played at, indexed like `TrackData::phrases`,
+/// phrases without a choice of their own use `default`, `None` plays the full chart
+#[derive(PartialEq, Clone, Debug, Default)]
+pub struct DifficultySelection {
+    pub default: Option<u8>,
+    pub phrases: HashMap<usize, u8>,
+}
+
+impl DifficultySelection {
+    pub fn full() -> DifficultySelection {
+        DifficultySelection::default()
+    }
+
+    pub fn all(difficulty: u8) -> DifficultySelection {
+        DifficultySelection {
+            default: Some(difficulty),
+            phrases: HashMap::new(),
+        }
+    }
+
+    pub fn with_phrase(mut self, phrase: usize, difficulty: u8) -> DifficultySelection {
+        self.phrases.insert(phrase, difficulty);
+        self
+    }
+
+    pub fn of(&self, phrase: usize) -> Option<u8> {
+        self.phrases.get(&phrase).cloned().or(self.default)
+    }
+}
+
+impl TrackData {
+    pub fn parse(content: &str) -> Result<TrackData, Vec<TrackParseError>> {
+        parse_track_data(content)
+    }
+
+    pub fn validate(&self, tuning: &Tuning, frets: u8) -> Vec<Diagnostic> {
+        validate(self, tuning, frets)
+    }
+}
+
+/// index of the first item of sorted `items` that doesn't match `before`
+fn first_not<T>(items: &[T], before: impl Fn(&T) -> bool) -> usize {
+    items
+        .binary_search_by(|item| if before(item) { Ordering::Less } else { Ordering::Greater })
+        .unwrap_or_else(|index| index)
+}
+
+pub fn fret(starts_at: u64, ends_at: u64, fret: u8, string: u8) -> Fret {
+    Fret {
         fret,
         string,
         starts_at: Duration::from_millis(starts_at),
         ends_at: Duration::from_millis(ends_at),
-    })
+        techniques: Techniques::default(),
+    }
+}
+
+pub fn fret_action(starts_at: u64, ends_at: u64, fret: u8, string: u8) -> Action {
+    Action::Fret(self::fret(starts_at, ends_at, fret, string))
 }
 
 pub fn hand_position(at: u64, fret: u8) -> HandPosition {
@@ -111,11 +522,29 @@ pub fn hand_position(at: u64, fret: u8) -> HandPosition {
     }
 }
 
+/// a chart of just `actions` and `hand_positions`, for tests
+#[cfg(test)]
+pub(crate) fn track_data(actions: Vec<Action>, hand_positions: Vec<HandPosition>) -> TrackData {
+    TrackData {
+        actions,
+        hand_positions,
+        tempo: TempoMap::default(),
+        sections: vec![],
+        phrases: vec![],
+        lyrics: vec![],
+    }
+}
+
+/// number of frets the hand covers without shifting, starting at the index finger
+pub const HAND_SPAN: u8 = 4;
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct SearchItem {
     pub name: String,
     pub id: String,
     pub youtube_id: String,
+    #[serde(default)]
+    pub stats: Option<TrackStats>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -166,13 +595,29 @@ impl SearchResponse {
 
 #[derive(Serialize, Deserialize)]
 pub enum TrackLoadResult {
-    Loaded(TrackData),
+    Loaded(Box<Arrangement>),
+    Error,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum ArrangementsLoadResult {
+    Loaded(Vec<ArrangementSummary>),
     Error,
 }
 
 #[derive(Serialize, Deserialize)]
 pub enum TrackCreateResult {
-    Created(String, Track),
+    Created(String, Box<Track>),
+    /// the text didn't parse, one message with line and column per broken line
+    Invalid(Vec<String>),
+    Error,
+}
+
+#[derive(Serialize, Deserialize)]
+pub enum TrackUpdateResult {
+    Updated(Box<Track>),
+    /// the uploaded file didn't parse or names no part of the track
+    Invalid(Vec<String>),
     Error,
 }
 
@@ -0,0 +1,285 @@
+use crate::ext::DurationExt;
+use crate::tempo::TempoMap;
+use crate::track::Fret;
+use crate::track::TrackData;
+use std::time::Duration;
+
+/// points in time notes are moved to when quantizing
+#[derive(PartialEq, Clone, Debug)]
+pub enum Grid {
+    /// every beat of the tempo map divided into this many parts
+    Beats(u32),
+    Millis(u64),
+}
+
+impl Grid {
+    fn snap(&self, tempo: &TempoMap, at: Duration) -> Duration {
+        match self {
+            Grid::Beats(subdivision) => tempo.snap(at, *subdivision),
+            Grid::Millis(resolution) => {
+                let resolution = (*resolution).max(1);
+                Duration::from_millis((at.total_millis() + resolution / 2) / resolution * resolution)
+            }
+        }
+    }
+
+    /// the grid point following `at`, which is on the grid
+    fn after(&self, tempo: &TempoMap, at: Duration) -> Duration {
+        match self {
+            Grid::Beats(subdivision) => {
+                let subdivision = f64::from((*subdivision).max(1));
+                tempo.time_of_beat(((tempo.beat_at(at) * subdivision).round() + 1.0) / subdivision)
+            }
+            Grid::Millis(resolution) => at + Duration::from_millis((*resolution).max(1)),
+        }
+    }
+}
+
+/// drop the points between `from` and `until`, unless `carry` keeps the last one in effect after them
+fn remove_points<T>(points: &mut Vec<T>, at: impl Fn(&T) -> Duration, from: Duration, until: Duration, carry: bool) {
+    let inside = |t: Duration| from <= t && t < until;
+    let carry = carry && !points.iter().any(|p| at(p) == until);
+    let last = points.iter().map(&at).filter(|t| carry && inside(*t)).max();
+    points.retain(|p| !inside(at(p)) || Some(at(p)) == last);
+}
+
+impl TrackData {
+    /// frets of the full chart and of every level
+    fn all_frets(&mut self) -> Vec<&mut Fret> {
+        let levels = self
+            .phrases
+            .iter_mut()
+            .flat_map(|p| p.levels.iter_mut())
+            .flat_map(|l| l.actions.iter_mut());
+        self.actions.iter_mut().chain(levels).flat_map(|a| a.frets_mut()).collect()
+    }
+
+    /// every time of the chart that isn't part of a note or the tempo map
+    fn points(&mut self) -> Vec<&mut Duration> {
+        let mut points: Vec<&mut Duration> = self.hand_positions.iter_mut().map(|p| &mut p.at).collect();
+        points.extend(self.sections.iter_mut().map(|s| &mut s.starts_at));
+        points.extend(self.lyrics.iter_mut().map(|l| &mut l.at));
+        for phrase in &mut self.phrases {
+            points.push(&mut phrase.starts_at);
+            points.push(&mut phrase.ends_at);
+            points.extend(phrase.levels.iter_mut().flat_map(|l| l.hand_positions.iter_mut()).map(|p| &mut p.at));
+        }
+        points
+    }
+
+    fn map_times(&mut self, f: impl Fn(Duration) -> Duration) {
+        for fret in self.all_frets() {
+            fret.starts_at = f(fret.starts_at);
+            fret.ends_at = f(fret.ends_at);
+        }
+        for point in self.points() {
+            *point = f(*point);
+        }
+        for tempo in &mut self.tempo.tempos {
+            tempo.at = f(tempo.at);
+        }
+    }
+
+    /// move everything by `offset` milliseconds, times before the start of the song become 0
+    pub fn shift(&mut self, offset: i64) {
+        self.map_times(|at| Duration::from_millis((at.total_millis() as i64 + offset).max(0) as u64));
+    }
+
+    /// make the song `factor` times as long, slowing the tempo down so notes stay on the beat
+    pub fn stretch(&mut self, factor: f64) {
+        if factor.is_nan() || factor <= 0.0 {
+            return;
+        }
+        self.map_times(|at| Duration::from_millis((at.total_millis() as f64 * factor).round() as u64));
+        for tempo in &mut self.tempo.tempos {
+            tempo.bpm /= factor;
+        }
+    }
+
+    /// move every time to the closest point of `grid`, notes that would end
+    /// before they start last until the next point
+    pub fn quantize(&mut self, grid: &Grid) {
+        let tempo = self.tempo.clone();
+        for fret in self.all_frets() {
+            fret.starts_at = grid.snap(&tempo, fret.starts_at);
+            fret.ends_at = grid.snap(&tempo, fret.ends_at).max(grid.after(&tempo, fret.starts_at));
+        }
+        for point in self.points() {
+            *point = grid.snap(&tempo, *point);
+        }
+    }
+
+    fn remove(&mut self, from: Duration, until: Duration, carry: bool) {
+        if until <= from {
+            return;
+        }
+        let gap = until - from;
+        let inside = |at: Duration| from <= at && at < until;
+        self.actions.retain(|a| !inside(*a.starts_at()));
+        self.lyrics.retain(|l| !inside(l.at));
+        for level in self.phrases.iter_mut().flat_map(|p| p.levels.iter_mut()) {
+            level.actions.retain(|a| !inside(*a.starts_at()));
+            remove_points(&mut level.hand_positions, |p| p.at, from, until, carry);
+        }
+        remove_points(&mut self.hand_positions, |p| p.at, from, until, carry);
+        remove_points(&mut self.sections, |s| s.starts_at, from, until, carry);
+        remove_points(&mut self.tempo.tempos, |t| t.at, from, until, carry);
+        self.map_times(|at| {
+            if at < from {
+                at
+            } else if at < until {
+                from
+            } else {
+                at - gap
+            }
+        });
+        self.phrases.retain(|p| p.starts_at < p.ends_at);
+    }
+
+    /// remove everything starting between `from` and `until` and close the gap, notes ringing into it
+    /// are cut short, the hand position, section and tempo in effect at `until` carry on from `from`
+    pub fn cut(&mut self, from: Duration, until: Duration) {
+        self.remove(from, until, true);
+    }
+
+    /// the part of the song between `from` and `until`, moved to start at 0
+    pub fn slice(&self, from: Duration, until: Duration) -> TrackData {
+        let mut slice = self.clone();
+        slice.remove(until, Duration::from_secs(u64::MAX), false);
+        slice.remove(Duration::from_millis(0), from, true);
+        slice
+    }
+
+    /// add the chart of `other` moved to start at `at`, the tempo map stays the one of this song
+    pub fn merge(&mut self, other: &TrackData, at: Duration) {
+        let mut other = other.clone();
+        other.shift(at.total_millis() as i64);
+        self.actions.extend(other.actions);
+        self.actions.sort_by_key(|a| *a.starts_at());
+        self.hand_positions.extend(other.hand_positions);
+        self.hand_positions.sort_by_key(|p| p.at);
+        self.sections.extend(other.sections);
+        self.sections.sort_by_key(|s| s.starts_at);
+        self.phrases.extend(other.phrases);
+        self.phrases.sort_by_key(|p| p.starts_at);
+        self.lyrics.extend(other.lyrics);
+        self.lyrics.sort_by_key(|l| l.at);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tempo::TempoChange;
+    use crate::track::fret_action;
+    use crate::track::hand_position;
+    use crate::track::track_data;
+    use crate::track::Action;
+    use crate::track::Lyric;
+    use crate::track::Section;
+
+    fn millis(millis: u64) -> Duration {
+        Duration::from_millis(millis)
+    }
+
+    fn track() -> TrackData {
+        let actions = vec![
+            fret_action(0, 400, 3, 6),
+            fret_action(510, 740, 5, 3),
+            fret_action(1000, 1600, 7, 3),
+            fret_action(2000, 2500, 5, 2),
+        ];
+        TrackData {
+            tempo: TempoMap {
+                tempos: vec![TempoChange { at: millis(0), bpm: 120.0 }],
+                time_signatures: vec![],
+            },
+            sections: vec![Section {
+                name: "verse".to_string(),
+                starts_at: millis(1000),
+            }],
+            ..track_data(actions, vec![hand_position(0, 3), hand_position(1000, 5), hand_position(2000, 4)])
+        }
+    }
+
+    fn times(data: &TrackData) -> Vec<(u64, u64)> {
+        data.actions
+            .iter()
+            .map(|a| (a.starts_at().total_millis(), a.ends_at().total_millis()))
+            .collect()
+    }
+
+    fn hands(data: &TrackData) -> Vec<(u64, u8)> {
+        data.hand_positions.iter().map(|p| (p.at.total_millis(), p.fret)).collect()
+    }
+
+    #[test]
+    fn test_shift() {
+        let mut data = track();
+        data.shift(-500);
+        assert_eq!(vec![(0, 0), (10, 240), (500, 1100), (1500, 2000)], times(&data));
+        assert_eq!(vec![(0, 3), (500, 5), (1500, 4)], hands(&data));
+        assert_eq!(millis(500), data.sections[0].starts_at);
+        data.shift(1000);
+        assert_eq!(millis(1000), data.tempo.tempos[0].at);
+    }
+
+    #[test]
+    fn test_stretch() {
+        let mut data = track();
+        data.stretch(2.0);
+        assert_eq!(vec![(0, 800), (1020, 1480), (2000, 3200), (4000, 5000)], times(&data));
+        assert_eq!(vec![(0, 3), (2000, 5), (4000, 4)], hands(&data));
+        assert_eq!(60.0, data.tempo.tempos[0].bpm);
+        // notes stay on the same beats
+        assert_eq!(4.0, data.tempo.beat_at(millis(4000)));
+    }
+
+    #[test]
+    fn test_quantize() {
+        let mut data = track();
+        data.quantize(&Grid::Millis(100));
+        assert_eq!(vec![(0, 400), (500, 700), (1000, 1600), (2000, 2500)], times(&data));
+        let mut data = track();
+        data.quantize(&Grid::Beats(1));
+        // the eighth note at 510 would vanish, it lasts a beat instead
+        assert_eq!(vec![(0, 500), (500, 1000), (1000, 1500), (2000, 2500)], times(&data));
+    }
+
+    #[test]
+    fn test_cut() {
+        let mut data = track();
+        data.cut(millis(500), millis(1500));
+        assert_eq!(vec![(0, 400), (1000, 1500)], times(&data));
+        // the hand position and section at 1000 carry on after the cut
+        assert_eq!(vec![(0, 3), (500, 5), (1000, 4)], hands(&data));
+        assert_eq!(millis(500), data.sections[0].starts_at);
+        let mut data = track();
+        let lyric = |at: u64| Lyric {
+            at: millis(at),
+            text: "la".to_string(),
+            line_end: false,
+        };
+        data.lyrics = vec![lyric(1300), lyric(1600)];
+        data.cut(millis(1200), millis(1500));
+        assert_eq!(vec![(0, 400), (510, 740), (1000, 1300), (1700, 2200)], times(&data));
+        assert_eq!(vec![lyric(1300)], data.lyrics);
+    }
+
+    #[test]
+    fn test_slice_and_merge() {
+        let data = track();
+        let slice = data.slice(millis(1000), millis(2000));
+        assert_eq!(vec![(0, 600)], times(&slice));
+        assert_eq!(vec![(0, 5)], hands(&slice));
+        assert_eq!(millis(0), slice.sections[0].starts_at);
+        let mut merged = data.slice(millis(0), millis(1000));
+        merged.merge(&slice, millis(3000));
+        assert_eq!(vec![(0, 400), (510, 740), (3000, 3600)], times(&merged));
+        assert_eq!(vec![(0, 3), (3000, 5)], hands(&merged));
+        match &merged.actions[2] {
+            Action::Fret(f) => assert_eq!(7, f.fret),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use crate::track::Fret;
+use std::fmt;
+use std::str::FromStr;
+
+const NOTE_NAMES: [&str; 12] = ["C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B"];
+
+const PRESETS: [(&str, &[u8]); 6] = [
+    ("standard", &[64, 59, 55, 50, 45, 40]),
+    ("drop-d", &[64, 59, 55, 50, 45, 38]),
+    ("e-flat", &[63, 58, 54, 49, 44, 39]),
+    ("seven-string", &[64, 59, 55, 50, 45, 40, 35]),
+    ("bass", &[43, 38, 33, 28]),
+    ("five-string-bass", &[43, 38, 33, 28, 23]),
+];
+
+/// open string pitches of an instrument and where the capo sits,
+/// frets of a track count from the capo
+#[derive(Serialize, Deserialize, PartialEq, Clone, Debug)]
+pub struct Tuning {
+    /// midi pitch of every open string, highest string first
+    pub strings: Vec<u8>,
+    #[serde(default)]
+    pub capo: u8,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning::standard()
+    }
+}
+
+impl Tuning {
+    pub fn new(strings: Vec<u8>) -> Tuning {
+        Tuning { strings, capo: 0 }
+    }
+
+    fn preset(name: &str) -> Option<Tuning> {
+        PRESETS.iter().find(|(n, _)| *n == name).map(|(_, strings)| Tuning::new(strings.to_vec()))
+    }
+
+    pub fn standard() -> Tuning {
+        Tuning::new(PRESETS[0].1.to_vec())
+    }
+
+    pub fn drop_d() -> Tuning {
+        Tuning::new(PRESETS[1].1.to_vec())
+    }
+
+    pub fn e_flat() -> Tuning {
+        Tuning::new(PRESETS[2].1.to_vec())
+    }
+
+    pub fn seven_string() -> Tuning {
+        Tuning::new(PRESETS[3].1.to_vec())
+    }
+
+    pub fn bass() -> Tuning {
+        Tuning::new(PRESETS[4].1.to_vec())
+    }
+
+    pub fn five_string_bass() -> Tuning {
+        Tuning::new(PRESETS[5].1.to_vec())
+    }
+
+    pub fn with_capo(self, capo: u8) -> Tuning {
+        Tuning { capo, ..self }
+    }
+
+    /// name of the preset with these strings, ignoring the capo
+    pub fn name(&self) -> Option<&'static str> {
+        PRESETS.iter().find(|(_, strings)| *strings == &self.strings[..]).map(|(name, _)| *name)
+    }
+
+    /// midi pitch of a string played open, capo included
+    pub fn open_pitch(&self, string: u8) -> Option<u8> {
+        let open = self.strings.get(usize::from(string).checked_sub(1)?)?;
+        Some(open.saturating_add(self.capo))
+    }
+
+    /// midi pitch sounding when `fret` is played, `None` for strings this tuning does not have
+    pub fn pitch_of(&self, fret: &Fret) -> Option<u8> {
+        self.open_pitch(fret.string).map(|open| open.saturating_add(fret.fret).min(127))
+    }
+
+    pub fn frequency_of(&self, fret: &Fret) -> Option<f64> {
+        self.pitch_of(fret).map(frequency)
+    }
+
+    /// the fret that plays `pitch` on `string`, if it can be reached at all
+    pub fn fret_for(&self, string: u8, pitch: u8) -> Option<u8> {
+        self.open_pitch(string).and_then(|open| pitch.checked_sub(open))
+    }
+}
+
+/// frequency in hertz of a midi pitch, a4 being 440 hertz
+pub fn frequency(pitch: u8) -> f64 {
+    440.0 * 2.0f64.powf((f64::from(pitch) - 69.0) / 12.0)
+}
+
+/// scientific pitch notation of a midi pitch, like `E2`
+pub fn pitch_name(pitch: u8) -> String {
+    format!("{}{}", NOTE_NAMES[usize::from(pitch % 12)], i32::from(pitch / 12) - 1)
+}
+
+fn parse_pitch(name: &str) -> Option<u8> {
+    let split = name.find(|c: char| c.is_ascii_digit() || c == '-')?;
+    let (note, octave) = name.split_at(split);
+    let mut chars = note.chars();
+    let base: i32 = match chars.next()?.to_ascii_uppercase() {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let accidental: i32 = match chars.as_str() {
+        "" => 0,
+        "#" => 1,
+        "b" => -1,
+        _ => return None,
+    };
+    let pitch = (octave.parse::<i32>().ok()? + 1) * 12 + base + accidental;
+    if (0..=127).contains(&pitch) {
+        Some(pitch as u8)
+    } else {
+        None
+    }
+}
+
+/// a preset name or the open strings as pitch names, highest string first
+impl FromStr for Tuning {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(tuning) = Tuning::preset(s.trim()) {
+            return Ok(tuning);
+        }
+        let strings = s.split_whitespace().map(parse_pitch).collect::<Option<Vec<u8>>>().ok_or(())?;
+        if strings.is_empty() {
+            return Err(());
+        }
+        Ok(Tuning::new(strings))
+    }
+}
+
+/// the capo is left out, it is written on its own
+impl fmt::Display for Tuning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(name) = self.name() {
+            return write!(f, "{}", name);
+        }
+        let names: Vec<String> = self.strings.iter().map(|p| pitch_name(*p)).collect();
+        write!(f, "{}", names.join(" "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::fret;
+
+    #[test]
+    fn test_pitch_of() {
+        let standard = Tuning::standard();
+        assert_eq!(Some(64), standard.pitch_of(&fret(0, 0, 0, 1)));
+        assert_eq!(Some(45), standard.pitch_of(&fret(0, 0, 5, 6)));
+        assert_eq!(None, standard.pitch_of(&fret(0, 0, 5, 7)));
+        assert_eq!(None, standard.pitch_of(&fret(0, 0, 5, 0)));
+        assert_eq!(Some(40), Tuning::drop_d().pitch_of(&fret(0, 0, 2, 6)));
+        assert_eq!(Some(47), Tuning::standard().with_capo(2).pitch_of(&fret(0, 0, 5, 6)));
+        assert_eq!(Some(7), Tuning::standard().fret_for(6, 47));
+        assert_eq!(None, Tuning::standard().fret_for(1, 47));
+        let a = standard.frequency_of(&fret(0, 0, 0, 5)).unwrap();
+        assert!((a - 110.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_tuning_text() {
+        assert_eq!(Ok(Tuning::e_flat()), "e-flat".parse());
+        assert_eq!("seven-string", Tuning::seven_string().to_string());
+        let open_g: Tuning = "D4 B3 G3 D3 G2 D2".parse().unwrap();
+        assert_eq!(vec![62, 59, 55, 50, 43, 38], open_g.strings);
+        assert_eq!("D4 B3 G3 D3 G2 D2", open_g.to_string());
+        assert_eq!(Ok(Tuning::e_flat()), "Eb4 Bb3 Gb3 Db3 Ab2 Eb2".parse());
+        assert_eq!(Err(()), "H2".parse::<Tuning>());
+        assert_eq!(Err(()), "".parse::<Tuning>());
+    }
+}
@@ -0,0 +1,271 @@
+use crate::ext::DurationExt;
+use crate::track::Action;
+use crate::track::HandPosition;
+use crate::track::TrackData;
+use crate::track::HAND_SPAN;
+use crate::tuning::Tuning;
+use std::fmt;
+use std::time::Duration;
+
+/// frets of a guitar neck when the instrument does not say otherwise
+pub const DEFAULT_FRETS: u8 = 24;
+
+/// strings the game can draw
+pub const MAX_STRINGS: u8 = 7;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Severity {
+    /// the chart plays, but not the way it was meant to
+    Warning,
+    /// the chart can't be played or rendered
+    Error,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum DiagnosticKind {
+    /// a note starts on a string still ringing from the action at this index
+    Overlap(usize),
+    EmptyNote,
+    /// counting from the capo
+    FretOutOfRange {
+        fret: u8,
+        frets: u8,
+    },
+    StringOutOfRange {
+        string: u8,
+        strings: u8,
+    },
+    Unsorted,
+    Unreachable {
+        fret: u8,
+        hand: u8,
+    },
+}
+
+/// a problem with the action at index `action` of a chart
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    pub kind: DiagnosticKind,
+    pub action: usize,
+    pub at: Duration,
+    /// phrase index and difficulty of the reduced level holding the action, none for the full chart
+    pub level: Option<(usize, u8)>,
+}
+
+impl Diagnostic {
+    pub fn severity(&self) -> Severity {
+        match self.kind {
+            DiagnosticKind::EmptyNote | DiagnosticKind::StringOutOfRange { .. } => Severity::Error,
+            _ => Severity::Warning,
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some((phrase, difficulty)) = self.level {
+            write!(f, "phrase {} level {} ", phrase, difficulty)?;
+        }
+        write!(f, "action {} at {}ms: ", self.action, self.at.total_millis())?;
+        match &self.kind {
+            DiagnosticKind::Overlap(other) => write!(f, "overlaps action {} on the same string", other),
+            DiagnosticKind::EmptyNote => write!(f, "ends before it starts"),
+            DiagnosticKind::FretOutOfRange { fret, frets } => write!(f, "fret {} is past the last fret {}", fret, frets),
+            DiagnosticKind::StringOutOfRange { string, strings } => write!(f, "string {} is not one of the {} strings", string, strings),
+            DiagnosticKind::Unsorted => write!(f, "starts before the action before it"),
+            DiagnosticKind::Unreachable { fret, hand } => write!(f, "fret {} is out of reach of the hand at fret {}", fret, hand),
+        }
+    }
+}
+
+/// check the full chart of `data` and every reduced level of its phrases against an instrument
+/// tuned to `tuning` with `frets` frets, diagnostics of the full chart come first, each chart's ordered by action
+pub fn validate(data: &TrackData, tuning: &Tuning, frets: u8) -> Vec<Diagnostic> {
+    let mut diagnostics = validate_actions(&data.actions, &data.hand_positions, tuning, frets, None);
+    for (index, phrase) in data.phrases.iter().enumerate() {
+        for level in &phrase.levels {
+            let location = Some((index, level.difficulty));
+            diagnostics.extend(validate_actions(&level.actions, &level.hand_positions, tuning, frets, location));
+        }
+    }
+    diagnostics
+}
+
+fn validate_actions(actions: &[Action], hand_positions: &[HandPosition], tuning: &Tuning, frets: u8, level: Option<(usize, u8)>) -> Vec<Diagnostic> {
+    // strings past the ones the game draws can't be shown whatever the tuning
+    let strings = (tuning.strings.len() as u8).min(MAX_STRINGS);
+    let frets = frets.saturating_sub(tuning.capo);
+    let mut diagnostics: Vec<Diagnostic> = vec![];
+    let mut report = |kind: DiagnosticKind, action: usize| {
+        diagnostics.push(Diagnostic {
+            kind,
+            action,
+            at: *actions[action].starts_at(),
+            level,
+        })
+    };
+
+    let mut hand_positions: Vec<&HandPosition> = hand_positions.iter().collect();
+    hand_positions.sort_by_key(|p| p.at);
+    // start, end, string and action of every fret, to find notes ringing into each other
+    let mut ringing: Vec<(Duration, Duration, u8, usize)> = vec![];
+    for (index, action) in actions.iter().enumerate() {
+        if index > 0 && action.starts_at() < actions[index - 1].starts_at() {
+            report(DiagnosticKind::Unsorted, index);
+        }
+        if action.frets().iter().any(|f| f.ends_at <= f.starts_at) {
+            report(DiagnosticKind::EmptyNote, index);
+        }
+        for fret in action.frets() {
+            if fret.string == 0 || fret.string > strings {
+                report(
+                    DiagnosticKind::StringOutOfRange {
+                        string: fret.string,
+                        strings,
+                    },
+                    index,
+                );
+            }
+            if fret.fret > frets {
+                report(DiagnosticKind::FretOutOfRange { fret: fret.fret, frets }, index);
+            }
+            ringing.push((fret.starts_at, fret.ends_at, fret.string, index));
+        }
+        let hand = hand_positions.iter().rev().find(|p| p.at <= *action.starts_at());
+        let fretted = action
+            .frets()
+            .into_iter()
+            .filter(|f| f.fret > 0 && !f.techniques.dead && !f.techniques.tap);
+        if let Some(hand) = hand {
+            if let Some(fret) = fretted
+                .map(|f| f.fret)
+                .find(|f| *f < hand.fret || *f >= hand.fret.saturating_add(HAND_SPAN))
+            {
+                report(DiagnosticKind::Unreachable { fret, hand: hand.fret }, index);
+            }
+        }
+    }
+
+    ringing.sort_by_key(|(starts_at, _, string, _)| (*string, *starts_at));
+    let mut last: Option<(Duration, u8, usize)> = None;
+    for (starts_at, ends_at, string, index) in ringing {
+        match last {
+            Some((end, s, other)) if s == string && other != index && starts_at < end => {
+                report(DiagnosticKind::Overlap(other), index);
+                if ends_at > end {
+                    last = Some((ends_at, string, index));
+                }
+            }
+            Some((end, s, _)) if s == string && ends_at <= end => (),
+            _ => last = Some((ends_at, string, index)),
+        }
+    }
+
+    diagnostics.sort_by_key(|d| d.action);
+    diagnostics.dedup();
+    diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::fret;
+    use crate::track::fret_action;
+    use crate::track::hand_position;
+    use crate::track::track_data;
+    use crate::track::Action;
+    use crate::track::Fret;
+    use crate::track::Level;
+    use crate::track::Phrase;
+    use crate::track::Techniques;
+
+    fn kinds(data: &TrackData) -> Vec<(usize, DiagnosticKind)> {
+        validate(data, &Tuning::standard(), DEFAULT_FRETS)
+            .into_iter()
+            .map(|d| (d.action, d.kind))
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_chart() {
+        let track = track_data(
+            vec![
+                fret_action(0, 500, 5, 3),
+                fret_action(500, 1000, 7, 3),
+                Action::Slide(fret(1000, 1200, 5, 2), fret(1200, 1500, 7, 2)),
+            ],
+            vec![hand_position(0, 5)],
+        );
+        assert_eq!(Vec::<(usize, DiagnosticKind)>::new(), kinds(&track));
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let tapped = Fret {
+            techniques: Techniques {
+                tap: true,
+                ..Techniques::default()
+            },
+            ..fret(3000, 3100, 19, 1)
+        };
+        let track = track_data(
+            vec![
+                fret_action(0, 500, 5, 3),
+                fret_action(400, 600, 7, 3),
+                fret_action(300, 300, 5, 2),
+                fret_action(1000, 1200, 25, 0),
+                fret_action(2000, 2200, 12, 1),
+                Action::Fret(tapped),
+            ],
+            vec![hand_position(0, 5)],
+        );
+        assert_eq!(
+            vec![
+                (1, DiagnosticKind::Overlap(0)),
+                (2, DiagnosticKind::Unsorted),
+                (2, DiagnosticKind::EmptyNote),
+                (3, DiagnosticKind::StringOutOfRange { string: 0, strings: 6 }),
+                (3, DiagnosticKind::FretOutOfRange { fret: 25, frets: 24 }),
+                (3, DiagnosticKind::Unreachable { fret: 25, hand: 5 }),
+                (4, DiagnosticKind::Unreachable { fret: 12, hand: 5 }),
+            ],
+            kinds(&track)
+        );
+        let diagnostics = validate(&track, &Tuning::standard().with_capo(5), DEFAULT_FRETS);
+        assert_eq!(Severity::Error, diagnostics[2].severity());
+        assert_eq!("action 3 at 1000ms: fret 25 is past the last fret 19", diagnostics[4].to_string());
+    }
+
+    #[test]
+    fn test_level_diagnostics() {
+        let mut track = track_data(vec![fret_action(0, 500, 5, 8)], vec![]);
+        track.phrases.push(Phrase {
+            name: "riff".to_string(),
+            starts_at: Duration::from_millis(0),
+            ends_at: Duration::from_millis(1000),
+            levels: vec![Level {
+                difficulty: 0,
+                actions: vec![fret_action(0, 500, 5, 3), fret_action(500, 1000, 5, 0)],
+                hand_positions: vec![],
+            }],
+        });
+        let eight_strings = Tuning {
+            strings: vec![64, 59, 55, 50, 45, 40, 35, 30],
+            capo: 0,
+        };
+        let diagnostics = validate(&track, &eight_strings, DEFAULT_FRETS);
+        let kinds: Vec<_> = diagnostics.iter().map(|d| (d.level, d.action, d.kind.clone())).collect();
+        assert_eq!(
+            vec![
+                (None, 0, DiagnosticKind::StringOutOfRange { string: 8, strings: 7 }),
+                (Some((0, 0)), 1, DiagnosticKind::StringOutOfRange { string: 0, strings: 7 }),
+            ],
+            kinds
+        );
+        assert!(diagnostics.iter().all(|d| d.severity() == Severity::Error));
+        assert_eq!(
+            "phrase 0 level 0 action 1 at 500ms: string 0 is not one of the 7 strings",
+            diagnostics[1].to_string()
+        );
+    }
+}
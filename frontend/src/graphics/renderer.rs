@@ -12,9 +12,12 @@ use crate::services::ext::WebGLRenderingContextExt;
 use nalgebra::*;
 use rustmith_common::ext::DurationExt;
 use rustmith_common::track::Action;
+use rustmith_common::track::DifficultySelection;
 use rustmith_common::track::Fret;
 use rustmith_common::track::TrackData;
+use rustmith_common::track::TrackIndex;
 use rustmith_common::track::TrackView;
+use rustmith_common::track::Window;
 use std::time::Duration;
 use stdweb::unstable::TryInto;
 use stdweb::web::document;
@@ -30,6 +33,9 @@ use yew::prelude::Env;
 use yew::prelude::Html;
 use yew::prelude::Renderable;
 
+/// lines of lyrics shown under the highway, the one being sung and those after it
+const LYRIC_LINES: usize = 2;
+
 pub struct Renderer {
     pub program: Program,
     pub camera: Option<Camera>,
@@ -46,7 +52,11 @@ pub struct RendererModel {
     game_time: f64,
     fps: FpsStats,
     fps_snapshot: FpsStats,
-    track: Option<TrackData>,
+    /// the chart and difficulty `track` was indexed from
+    props: RendererProps,
+    track: Option<TrackIndex>,
+    window: Window,
+    lyrics: Vec<String>,
 }
 
 pub enum RendererMessage {
@@ -57,11 +67,22 @@ pub enum RendererMessage {
 #[derive(Clone, PartialEq)]
 pub struct RendererProps {
     pub track: Option<TrackData>,
+    pub difficulty: DifficultySelection,
 }
 
 impl Default for RendererProps {
     fn default() -> Self {
-        RendererProps { track: None }
+        RendererProps {
+            track: None,
+            difficulty: DifficultySelection::full(),
+        }
+    }
+}
+
+impl RendererProps {
+    /// indexing the chart once keeps every frame from going over the whole song
+    fn index(&self) -> Option<TrackIndex> {
+        self.track.as_ref().map(|t| TrackIndex::new(t, &self.difficulty))
     }
 }
 
@@ -77,7 +98,10 @@ impl Component<Registry> for RendererModel {
             job: RendererModel::animate(env),
             fps: FpsStats::new(),
             fps_snapshot: FpsStats::new(),
-            track: props.track,
+            track: props.index(),
+            window: Window::default(),
+            lyrics: vec![],
+            props,
         }
     }
 
@@ -88,8 +112,14 @@ impl Component<Registry> for RendererModel {
                     self.renderer = self.setup_graphics(env);
                 }
                 let delta_millis = time - self.last_time.unwrap_or(time);
+                let mut lyrics_changed = false;
                 if let (Some(r), Some(track)) = (&mut self.renderer, &self.track) {
-                    let track_view = track.view(Duration::from_millis(self.game_time as u64));
+                    let track_view = track.view(Duration::from_millis(self.game_time as u64), &self.window);
+                    let lyrics: Vec<String> = track_view.lyrics.iter().take(LYRIC_LINES).map(|l| l.text.clone()).collect();
+                    if lyrics != self.lyrics {
+                        self.lyrics = lyrics;
+                        lyrics_changed = true;
+                    }
                     r.render(delta_millis, self.game_time, track_view);
                 } else {
                     env.console.warn("Something is wrong, renderer not found");
@@ -103,7 +133,7 @@ impl Component<Registry> for RendererModel {
                     self.fps.drain(&mut self.fps_snapshot);
                     true
                 } else {
-                    false
+                    lyrics_changed
                 }
             }
 
@@ -120,7 +150,10 @@ impl Component<Registry> for RendererModel {
     }
 
     fn change(&mut self, props: Self::Properties, _env: &mut Env<Registry, Self>) -> bool {
-        self.track = props.track;
+        if props != self.props {
+            self.track = props.index();
+            self.props = props;
+        }
         false
     }
 }
@@ -152,34 +185,43 @@ impl Renderer {
 
         for action in track_view.actions {
             match action {
-                Action::Fret(Fret { fret, string, .. }) => {
-                    // Position
-                    self.context
-                        .bind_buffer(gl::ARRAY_BUFFER, Some(&self.frets[*string as usize - 1].vertex_buffer));
-                    self.context.vertex_attrib_pointer(self.program.position, 3, gl::FLOAT, false, 0, 0);
-                    self.context.enable_vertex_attrib_array(self.program.position);
+                Action::Fret(_) | Action::Chord(_) => {
+                    for Fret {
+                        fret,
+                        string,
+                        starts_at,
+                        ends_at,
+                        ..
+                    } in action.frets()
+                    {
+                        // Position
+                        self.context
+                            .bind_buffer(gl::ARRAY_BUFFER, Some(&self.frets[*string as usize - 1].vertex_buffer));
+                        self.context.vertex_attrib_pointer(self.program.position, 3, gl::FLOAT, false, 0, 0);
+                        self.context.enable_vertex_attrib_array(self.program.position);
 
-                    // Color
-                    self.context
-                        .bind_buffer(gl::ARRAY_BUFFER, Some(&self.frets[*string as usize - 1].color_buffer));
-                    self.context.vertex_attrib_pointer(self.program.color, 3, gl::FLOAT, false, 0, 0);
-                    self.context.enable_vertex_attrib_array(self.program.color);
+                        // Color
+                        self.context
+                            .bind_buffer(gl::ARRAY_BUFFER, Some(&self.frets[*string as usize - 1].color_buffer));
+                        self.context.vertex_attrib_pointer(self.program.color, 3, gl::FLOAT, false, 0, 0);
+                        self.context.enable_vertex_attrib_array(self.program.color);
 
-                    // Indices
-                    self.context
-                        .bind_buffer(gl::ELEMENT_ARRAY_BUFFER, Some(&self.frets[*string as usize - 1].index_buffer));
+                        // Indices
+                        self.context
+                            .bind_buffer(gl::ELEMENT_ARRAY_BUFFER, Some(&self.frets[*string as usize - 1].index_buffer));
 
-                    let mut model_matrix: Matrix4<f32> = Matrix4::identity();
-                    let x = f32::from(*fret);
-                    let y = f32::from(*string) * 0.5 - 5.0;
-                    let z = (action.starts_at().total_millis() as f32 - game_time as f32) * 0.001 as f32;
-                    let length = (action.ends_at().total_millis() - action.starts_at().total_millis()) as f32 * 0.001;
-                    model_matrix *= Matrix4::new_translation(&Vector3::new(x, y, -z));
-                    model_matrix *= Matrix4::from_diagonal(&Vector4::new(0.5, 0.25, 0.5 * length, 1.0));
-                    model_matrix *= Matrix4::new_translation(&Vector3::new(0.0, 0.0, -1.0));
-                    self.context
-                        .uniform_matrix4fv(Some(&self.program.model_matrix_location), false, &model_matrix.as_slice()[..]);
-                    self.context.draw_elements(gl::TRIANGLES, 36, gl::UNSIGNED_SHORT, 0);
+                        let mut model_matrix: Matrix4<f32> = Matrix4::identity();
+                        let x = f32::from(*fret);
+                        let y = f32::from(*string) * 0.5 - 5.0;
+                        let z = (starts_at.total_millis() as f32 - game_time as f32) * 0.001 as f32;
+                        let length = ends_at.total_millis().saturating_sub(starts_at.total_millis()) as f32 * 0.001;
+                        model_matrix *= Matrix4::new_translation(&Vector3::new(x, y, -z));
+                        model_matrix *= Matrix4::from_diagonal(&Vector4::new(0.5, 0.25, 0.5 * length, 1.0));
+                        model_matrix *= Matrix4::new_translation(&Vector3::new(0.0, 0.0, -1.0));
+                        self.context
+                            .uniform_matrix4fv(Some(&self.program.model_matrix_location), false, &model_matrix.as_slice()[..]);
+                        self.context.draw_elements(gl::TRIANGLES, 36, gl::UNSIGNED_SHORT, 0);
+                    }
                 }
                 _ => {
                     js! { console.log("unsupported action"); };
@@ -227,12 +269,21 @@ impl Renderable<Registry, RendererModel> for RendererModel {
           <>
             <FpsModel: fps=&self.fps_snapshot, />
             <canvas id="canvas",></canvas>
+            <div class="lyrics",>
+              { for self.lyrics.iter().map(|line| self.lyric_view(line)) }
+            </div>
           </>
         }
     }
 }
 
 impl RendererModel {
+    fn lyric_view(&self, line: &str) -> Html<Registry, RendererModel> {
+        html! {
+          <div class="lyric-line",> { line } </div>
+        }
+    }
+
     fn animate(env: &mut Env<Registry, Self>) -> Box<RequestAnimationFrameHandle> {
         let send_back = env.send_back(|time| RendererMessage::Animate { time });
         let f = move |d| {
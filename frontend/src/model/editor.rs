@@ -1,8 +1,10 @@
 use crate::registry::Registry;
 use crate::services::track::make_youtube_url;
 use crate::services::track::TrackService;
+use rustmith_common::fingering::plan_hand;
+use rustmith_common::track::Track;
 use rustmith_common::track::TrackCreateResult;
-use rustmith_common::track::TrackData;
+use rustmith_common::validate::Severity;
 use stdweb::unstable::TryInto;
 use yew::prelude::*;
 use yew::services::fetch::FetchTask;
@@ -111,18 +113,51 @@ impl Component<Registry> for EditorModel {
                 }
                 true
             }
-            EditorMessage::SaveAndExit => match (&self.song_content, &self.song_name, &self.song_youtube_id) {
-                (Some(content), Some(name), Some(youtube_id)) => {
-                    let callback = context.send_back(|r: TrackCreateResult| match r {
-                        TrackCreateResult::Created(id, _) => EditorMessage::Route(RoutingMessage::ExitAndShowTrack(id)),
-                        TrackCreateResult::Error => EditorMessage::Error("create error"),
-                    });
-                    if let Ok(data) = TrackData::parse(content) {
-                        self.task = Some(context.track.create_track(name, youtube_id, data, callback));
+            EditorMessage::SaveAndExit => match &self.song_content {
+                Some(content) => {
+                    match Track::parse(content) {
+                        Ok(mut track) => {
+                            if let Some(name) = &self.song_name {
+                                track.name = name.clone();
+                            }
+                            if let Some(youtube_id) = &self.song_youtube_id {
+                                track.youtube_id = youtube_id.clone();
+                            }
+                            if track.name.is_empty() || track.youtube_id.is_empty() {
+                                context.console.error("Not all fields are set");
+                                return false;
+                            }
+                            for arrangement in &mut track.arrangements {
+                                if arrangement.data.hand_positions.is_empty() {
+                                    arrangement.data.hand_positions = plan_hand(&arrangement.data.actions);
+                                }
+                            }
+                            let diagnostics = track.validate();
+                            for (index, diagnostic) in &diagnostics {
+                                let message = format!("{}: {}", track.arrangements[*index].name, diagnostic);
+                                match diagnostic.severity() {
+                                    Severity::Error => context.console.error(&message),
+                                    Severity::Warning => context.console.warn(&message),
+                                }
+                            }
+                            if diagnostics.iter().any(|(_, d)| d.severity() == Severity::Error) {
+                                return false;
+                            }
+                            let callback = context.send_back(|r: TrackCreateResult| match r {
+                                TrackCreateResult::Created(id, _) => EditorMessage::Route(RoutingMessage::ExitAndShowTrack(id)),
+                                TrackCreateResult::Invalid(_) | TrackCreateResult::Error => EditorMessage::Error("create error"),
+                            });
+                            self.task = Some(context.track.create_track(track, callback));
+                        }
+                        Err(errors) => {
+                            for error in errors {
+                                context.console.error(&error.to_string());
+                            }
+                        }
                     }
                     true
                 }
-                _ => {
+                None => {
                     context.console.error("Not all fields are set");
                     false
                 }
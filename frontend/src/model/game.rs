@@ -1,6 +1,9 @@
 use crate::registry::Registry;
 use crate::services::ext::WindowExt;
 use crate::services::track::TrackService;
+use rustmith_common::track::ArrangementSummary;
+use rustmith_common::track::ArrangementsLoadResult;
+use rustmith_common::track::DifficultySelection;
 use rustmith_common::track::TrackData;
 use rustmith_common::track::TrackLoadResult;
 use stdweb::web::window;
@@ -18,7 +21,12 @@ pub enum RoutingMessage {
 pub enum GameMessage {
     Route(RoutingMessage),
     ConnectMicrophone(MediaStream),
+    ArrangementsReceived(ArrangementsLoadResult),
+    PickArrangement(usize),
     TrackReceived(TrackLoadResult),
+    /// play every phrase at this difficulty, `None` for the full chart
+    SetDifficulty(Option<u8>),
+    SetPhraseDifficulty(usize, u8),
 }
 
 pub struct GameStats {
@@ -29,10 +37,12 @@ pub struct GameStats {
 
 pub struct GameModel {
     on_signal: Option<Callback<RoutingMessage>>,
-    #[allow(dead_code)]
     song_id: Option<String>,
     pub song_url: Option<String>,
+    /// what the song can be played as, one is picked before the chart is loaded
+    pub arrangements: Vec<ArrangementSummary>,
     pub track: Option<TrackData>,
+    pub difficulty: DifficultySelection,
     pub stats: GameStats,
     pub mic: Option<MediaStreamSource>,
     task: Option<FetchTask>,
@@ -64,13 +74,15 @@ impl Component<Registry> for GameModel {
         GameModel::fetch_mic(env);
         let mut task: Option<FetchTask> = None;
         if let Some(song_id) = &props.songid {
-            task = Some(GameModel::fetch_track(env, song_id));
+            task = Some(GameModel::fetch_arrangements(env, song_id));
         }
         GameModel {
             on_signal: props.onsignal,
             song_id: props.songid,
             song_url: props.songurl,
+            arrangements: vec![],
             track: None,
+            difficulty: DifficultySelection::full(),
             stats: GameStats {
                 notes_missed: 0,
                 notes_hit: 0,
@@ -98,9 +110,41 @@ impl Component<Registry> for GameModel {
                 self.mic = Some(mic);
                 true
             }
-            GameMessage::TrackReceived(TrackLoadResult::Loaded(track)) => {
+            GameMessage::ArrangementsReceived(ArrangementsLoadResult::Loaded(arrangements)) => {
                 self.task = None;
-                self.track = Some(track);
+                // nothing to pick from with a single arrangement
+                if arrangements.len() == 1 {
+                    self.update(GameMessage::PickArrangement(0), env);
+                }
+                self.arrangements = arrangements;
+                true
+            }
+            GameMessage::ArrangementsReceived(ArrangementsLoadResult::Error) => {
+                self.task = None;
+                env.console.warn(&format!("Unable to load arrangements of {:?}", &self.song_id));
+                true
+            }
+            GameMessage::PickArrangement(index) => {
+                if let Some(song_id) = &self.song_id {
+                    self.task = Some(GameModel::fetch_track(env, song_id, index));
+                }
+                false
+            }
+            GameMessage::TrackReceived(TrackLoadResult::Loaded(arrangement)) => {
+                self.task = None;
+                self.track = Some(arrangement.data);
+                self.difficulty = DifficultySelection::full();
+                true
+            }
+            GameMessage::SetDifficulty(difficulty) => {
+                self.difficulty = match difficulty {
+                    Some(difficulty) => DifficultySelection::all(difficulty),
+                    None => DifficultySelection::full(),
+                };
+                true
+            }
+            GameMessage::SetPhraseDifficulty(phrase, difficulty) => {
+                self.difficulty = self.difficulty.clone().with_phrase(phrase, difficulty);
                 true
             }
             GameMessage::TrackReceived(TrackLoadResult::Error) => {
@@ -113,16 +157,24 @@ impl Component<Registry> for GameModel {
 
     fn change(&mut self, props: Self::Properties, env: &mut Env<Registry, Self>) -> bool {
         if let Some(song_id) = &props.songid {
-            self.task = Some(GameModel::fetch_track(env, song_id));
+            self.arrangements = vec![];
+            self.track = None;
+            self.task = Some(GameModel::fetch_arrangements(env, song_id));
         }
+        self.song_id = props.songid;
         false
     }
 }
 
 impl GameModel {
-    fn fetch_track(env: &mut Env<Registry, GameModel>, song_id: &str) -> FetchTask {
+    fn fetch_arrangements(env: &mut Env<Registry, GameModel>, song_id: &str) -> FetchTask {
+        let on_arrangements = env.send_back(GameMessage::ArrangementsReceived);
+        env.track.load_arrangements(song_id, on_arrangements)
+    }
+
+    fn fetch_track(env: &mut Env<Registry, GameModel>, song_id: &str, arrangement: usize) -> FetchTask {
         let on_song = env.send_back(GameMessage::TrackReceived);
-        env.track.load_track(song_id, on_song)
+        env.track.load_track(song_id, arrangement, on_song)
     }
 }
 
@@ -1,8 +1,8 @@
 use failure::Error;
+use rustmith_common::track::ArrangementsLoadResult;
 use rustmith_common::track::SearchResponse;
 use rustmith_common::track::Track;
 use rustmith_common::track::TrackCreateResult;
-use rustmith_common::track::TrackData;
 use rustmith_common::track::TrackLoadResult;
 use yew::format::Json;
 use yew::format::Nothing;
@@ -13,8 +13,9 @@ use yew::services::fetch::Request;
 use yew::services::fetch::Response;
 
 pub trait TrackService {
-    fn create_track(&mut self, name: &str, youtube_id: &str, data: TrackData, callback: Callback<TrackCreateResult>) -> FetchTask;
-    fn load_track(&mut self, track_id: &str, callback: Callback<TrackLoadResult>) -> FetchTask;
+    fn create_track(&mut self, track: Track, callback: Callback<TrackCreateResult>) -> FetchTask;
+    fn load_arrangements(&mut self, track_id: &str, callback: Callback<ArrangementsLoadResult>) -> FetchTask;
+    fn load_track(&mut self, track_id: &str, arrangement: usize, callback: Callback<TrackLoadResult>) -> FetchTask;
     fn search(&mut self, term: &str, continuation_token: Option<&String>, callback: Callback<SearchResponse>) -> FetchTask;
 }
 
@@ -29,13 +30,7 @@ pub struct RemoteTrackService {
 //}
 
 impl TrackService for RemoteTrackService {
-    fn create_track(&mut self, name: &str, youtube_id: &str, data: TrackData, callback: Callback<TrackCreateResult>) -> FetchTask {
-        let track = Track {
-            id: "".to_string(),
-            name: name.to_string(),
-            youtube_id: youtube_id.to_string(),
-            data,
-        };
+    fn create_track(&mut self, track: Track, callback: Callback<TrackCreateResult>) -> FetchTask {
         let request = Request::post("http://localhost:8000/tracks")
             .header("Content-Type", "application/json")
             .body::<Json<&Track>>(Json(&track))
@@ -52,11 +47,28 @@ impl TrackService for RemoteTrackService {
         )
     }
 
-    fn load_track(&mut self, track_id: &str, callback: Callback<TrackLoadResult>) -> FetchTask {
+    fn load_arrangements(&mut self, track_id: &str, callback: Callback<ArrangementsLoadResult>) -> FetchTask {
         let request = Request::get(&format!("http://localhost:8000/tracks/{}", track_id))
             .header("Content-Type", "application/json")
             .body(Nothing)
             .expect("Failed to build request.");
+        self.http.fetch(
+            request,
+            Callback::from(move |response: Response<Json<Result<ArrangementsLoadResult, Error>>>| {
+                let (_meta, Json(body)) = response.into_parts();
+                match body {
+                    Ok(r) => callback.emit(r),
+                    Err(_e) => callback.emit(ArrangementsLoadResult::Error),
+                }
+            }),
+        )
+    }
+
+    fn load_track(&mut self, track_id: &str, arrangement: usize, callback: Callback<TrackLoadResult>) -> FetchTask {
+        let request = Request::get(&format!("http://localhost:8000/tracks/{}/arrangements/{}", track_id, arrangement))
+            .header("Content-Type", "application/json")
+            .body(Nothing)
+            .expect("Failed to build request.");
         self.http.fetch(
             request,
             Callback::from(move |response: Response<Json<Result<TrackLoadResult, Error>>>| {
@@ -3,6 +3,8 @@ use crate::model::game::*;
 use crate::model::guitar_effects::GuitarEffectsModel;
 use crate::model::tuner::TunerModel;
 use crate::registry::Registry;
+use rustmith_common::track::ArrangementSummary;
+use rustmith_common::track::Phrase;
 use yew::prelude::*;
 
 impl Renderable<Registry, GameModel> for GameModel {
@@ -11,7 +13,9 @@ impl Renderable<Registry, GameModel> for GameModel {
           <div class="game",>
             <div class="game-view",>
               <button id="exit-button", onclick = |_| GameMessage::Route(RoutingMessage::ExitGame),> { "exit" } </button>
-              <RendererModel: track=&self.track, />
+              { self.arrangements_view() }
+              { self.difficulty_view() }
+              <RendererModel: track=&self.track, difficulty=&self.difficulty, />
             </div>
             <div class="game-video",>
               <iframe id="video-clip",
@@ -37,3 +41,77 @@ impl Renderable<Registry, GameModel> for GameModel {
         }
     }
 }
+
+impl GameModel {
+    fn arrangement_view(&self, index: usize, arrangement: &ArrangementSummary) -> Html<Registry, GameModel> {
+        let tuning = arrangement.tuning.name().unwrap_or("custom");
+        html! {
+          <button onclick=|_| GameMessage::PickArrangement(index),>
+            { format!("{} ({:?}, {})", arrangement.name, arrangement.instrument, tuning) }
+          </button>
+        }
+    }
+
+    fn arrangements_view(&self) -> Html<Registry, GameModel> {
+        if self.track.is_none() && self.arrangements.len() > 1 {
+            html! {
+              <div class="game-arrangements",>
+                { for self.arrangements.iter().enumerate().map(|(index, a)| self.arrangement_view(index, a)) }
+              </div>
+            }
+        } else {
+            html! {
+              <div class="game-arrangements",></div>
+            }
+        }
+    }
+
+    fn level_view(&self, difficulty: Option<u8>) -> Html<Registry, GameModel> {
+        let label = match difficulty {
+            Some(difficulty) => format!("level {}", difficulty),
+            None => "full".to_string(),
+        };
+        html! {
+          <button onclick=|_| GameMessage::SetDifficulty(difficulty),> { label } </button>
+        }
+    }
+
+    fn phrase_level_view(&self, phrase: usize, difficulty: u8, current: u8) -> Html<Registry, GameModel> {
+        let class = if difficulty == current { "selected" } else { "" };
+        html! {
+          <button class=class, onclick=|_| GameMessage::SetPhraseDifficulty(phrase, difficulty),> { difficulty.to_string() } </button>
+        }
+    }
+
+    /// buttons for one phrase from its easiest level up to the full chart
+    fn phrase_difficulty_view(&self, index: usize, phrase: &Phrase) -> Html<Registry, GameModel> {
+        let max = phrase.max_difficulty();
+        let current = self.difficulty.of(index).map(|d| d.min(max)).unwrap_or(max);
+        html! {
+          <div class="game-phrase-difficulty",>
+            { phrase.name.clone() }
+            { for (0..=max).map(|d| self.phrase_level_view(index, d, current)) }
+          </div>
+        }
+    }
+
+    /// the same difficulty for the whole song, or one per phrase that has reduced levels
+    fn difficulty_view(&self) -> Html<Registry, GameModel> {
+        let phrases = match &self.track {
+            Some(track) if track.phrases.iter().any(|p| !p.levels.is_empty()) => &track.phrases,
+            _ => {
+                return html! {
+                  <div class="game-difficulty",></div>
+                }
+            }
+        };
+        let hardest = phrases.iter().map(Phrase::max_difficulty).max().unwrap_or(0);
+        html! {
+          <div class="game-difficulty",>
+            { self.level_view(None) }
+            { for (0..hardest).map(|d| self.level_view(Some(d))) }
+            { for phrases.iter().enumerate().filter(|(_, p)| !p.levels.is_empty()).map(|(index, p)| self.phrase_difficulty_view(index, p)) }
+          </div>
+        }
+    }
+}
@@ -39,7 +39,10 @@ impl SearchModel {
     fn item_view(&self, item: &SearchItem) -> Html<Registry, SearchModel> {
         let id = item.id.clone();
         let url = make_youtube_url(&item.youtube_id);
-        let name = item.name.clone();
+        let name = match &item.stats {
+            Some(stats) => format!("{} (difficulty {:.0})", item.name, stats.difficulty),
+            None => item.name.clone(),
+        };
         html! {
           <div>
             { name }